@@ -0,0 +1,40 @@
+//! Hashes panda's own sources, so that a build made by a different version of panda doesn't
+//! reuse the pages of the previous one.
+
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+fn main() {
+    let mut files = vec![PathBuf::from("Cargo.toml"), PathBuf::from("build.rs")];
+    sources(Path::new("src"), &mut files);
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for file in files.iter() {
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(std::fs::read(file).unwrap_or_default());
+        hasher.update([0]);
+    }
+
+    println!("cargo:rustc-env=PANDA_SOURCE_HASH={:x}", hasher.finalize());
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!("cargo:rerun-if-changed=build.rs");
+}
+
+/// Every file inside `dir`, recursively.
+fn sources(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.is_dir() {
+            sources(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
@@ -1,10 +1,15 @@
+use crate::log::{debug, info, warning};
 use crate::*;
 use comrak::{
-    Options, PluginsBuilder, RenderPluginsBuilder, adapters, markdown_to_html_with_plugins, plugins,
+    Arena, Options, PluginsBuilder, RenderPluginsBuilder, adapters, format_html_with_plugins,
+    nodes::{AstNode, NodeHtmlBlock, NodeValue},
+    parse_document,
+    plugins::syntect::SyntectAdapter,
 };
-use std::collections::HashMap;
-use std::fs::File;
-use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
 use walkdir::WalkDir;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -12,117 +17,515 @@ struct GeneratedHtml {
     to: PathBuf,
     from: PathBuf,
     content: String,
+    ids: BTreeSet<String>, // Ids of elements which can be linked to with a fragment
+}
+
+/// A markdown file rendered in the parallel phase of [`Site::generate`], waiting to be merged
+/// into the site.
+enum RenderedPage {
+    Draft,
+    CategoryIntro(PathBuf, Page),
+    Page {
+        from: PathBuf,
+        to: PathBuf,
+        page: Page,
+        html: String,
+        entry: ManifestEntry,
+        item: Box<::rss::Item>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct AssetFile {
     to: PathBuf,
     from: PathBuf,
+    input: String, // Hash of the source file
 }
 
 pub struct Site {
-    dest: PathBuf,                          // Path to dest dir
-    root: PathBuf,                          // Path to root dir
-    assets: HashMap<PathBuf, AssetFile>,    // Key is the new path
+    config: Config,
+    templates: Templates,
+    output: PathBuf,                                   // Path to the final output dir
+    dest: PathBuf,                       // Path to dest dir, where the build is staged
+    root: PathBuf,                       // Path to root dir
+    assets: HashMap<PathBuf, AssetFile>, // Key is the new path
     pages: HashMap<PathBuf, GeneratedHtml>, // Key is the new path
-    rss_feed: ::rss::ChannelBuilder,
+    feed: Vec<PageSummary>,              // Pages of the main feed, sorted once all are known
+    tags: BTreeMap<String, Tag>,         // Key is the tag's slug
+    categories: HashMap<String, Vec<PageSummary>>, // Key is the category's dir
+    category_intros: HashMap<String, (PathBuf, Page)>, // Hand-written part of category indexes
     relative_links: Vec<RelativeLink>,
     url_links: Option<Vec<UrlLink>>,
-}
-
-impl Default for Site {
-    fn default() -> Self {
-        let root = PathBuf::from("../src");
-        let to = PathBuf::from("../output");
-        Site::new(to, root, None)
-    }
+    errors: Vec<BuildError>, // Per-file errors, reported together once the build ends
+    failed: HashSet<PathBuf>, // Pages which couldn't be generated, keyed like `pages`
+    manifest: Manifest,      // What this build's outputs are made from
+    previous: Manifest,      // What the outputs of the build in `output` were made from
 }
 
 impl Site {
-    pub fn new(dest: PathBuf, root: PathBuf, url_sender: Option<Vec<UrlLink>>) -> Self {
+    pub fn new(config: Config, url_sender: Option<Vec<UrlLink>>) -> Self {
         Self {
-            dest,
-            root,
+            dest: staging_dir(&config.output),
+            output: config.output.clone(),
+            root: config.source.clone(),
             assets: HashMap::new(),
             pages: HashMap::new(),
+            tags: BTreeMap::new(),
+            categories: HashMap::new(),
+            category_intros: HashMap::new(),
             relative_links: Vec::new(),
             url_links: url_sender,
-            rss_feed: ::rss::ChannelBuilder::default()
-                .title("Eduardo's blog")
-                .link("https://eduardorittner.github.io")
-                .description("My blog")
-                .language("en-us".to_owned())
-                .docs("https://www.rssboard.org/rss-specification".to_owned())
-                .to_owned(),
+            errors: Vec::new(),
+            failed: HashSet::new(),
+            manifest: Manifest::new(&config),
+            previous: Manifest::load(&manifest_file(&config.output)),
+            feed: Vec::new(),
+            templates: Templates::new(config.templates.clone()),
+            config,
+        }
+    }
+
+    pub fn build(config: Config) -> Result<(), BuildError> {
+        Site::build_inner(config, None, true)
+    }
+
+    /// Builds the site, also checking every external link with `validator`.
+    pub fn build_with_url_validator(
+        config: Config,
+        validator: &ExternalLinkValidator,
+    ) -> Result<(), BuildError> {
+        Site::build_inner(config, Some(validator), true)
+    }
+
+    /// Builds the site without replacing the output, failing on every problem found, including
+    /// broken external links if there's a `validator`.
+    pub fn check(
+        mut config: Config,
+        validator: Option<&ExternalLinkValidator>,
+    ) -> Result<(), BuildError> {
+        config.links.strict = true;
+        Site::build_inner(config, validator, false)?;
+        info!("No problems found");
+        Ok(())
+    }
+
+    /// Removes the output directory, along with its manifest and any build left staged next
+    /// to it.
+    pub fn clean(config: &Config) -> Result<(), BuildError> {
+        let dirs = [
+            config.output.clone(),
+            staging_dir(&config.output),
+            sibling_dir(&config.output, "old"),
+        ];
+
+        for dir in dirs.iter().filter(|dir| dir.exists()) {
+            debug!("Removing {}", dir.display());
+            std::fs::remove_dir_all(dir).map_err(BuildError::IoError)?;
         }
+
+        remove_manifest(&config.output)
     }
 
-    pub fn build(dest: PathBuf, root: PathBuf) -> Result<(), BuildError> {
-        let mut site = Site::new(dest, root, None);
+    fn build_inner(
+        config: Config,
+        validator: Option<&ExternalLinkValidator>,
+        commit: bool,
+    ) -> Result<(), BuildError> {
+        let mut site = Site::new(config, None);
+
+        let result = site.generate().and_then(|_| {
+            if let Err(e) = site.validate_internal_links() {
+                if site.config.links.strict {
+                    site.errors.push(e);
+                } else {
+                    // Invalid links are only warnings outside of strict mode
+                    warn(&e);
+                }
+            }
+
+            // Only checks look at the feeds, builds trust that they're written right
+            if !commit && let Err(e) = site.validate_feeds() {
+                site.errors.push(e);
+            }
+
+            if let Some(validator) = validator
+                && let Err(e) = site.validate_external_links(validator)
+            {
+                if site.config.links.strict {
+                    site.errors.push(e);
+                } else {
+                    warn(&e);
+                }
+            }
+
+            if !site.errors.is_empty() {
+                return Err(BuildError::Many(std::mem::take(&mut site.errors)));
+            }
+
+            if commit { site.commit_build() } else { Ok(()) }
+        });
 
-        site.generate().unwrap();
-        // Invalid links are only warnings for now
-        if let Err(e) = site.validate_internal_links() {
-            eprintln!("{:?}", e);
+        if result.is_err() || !commit {
+            site.discard_build();
         }
 
-        site.commit_build()
+        result
     }
 
-    /// Generates all html pages and validates internal links
+    /// Generates all html pages into the staging directory.
+    ///
+    /// Only fails if the staging directory can't be written to: a page which can't be
+    /// generated is skipped, and its error kept to be reported along with the others.
     pub fn generate(&mut self) -> Result<(), BuildError> {
-        println!("Starting build");
+        info!("Starting build");
 
-        if !self.dest.exists() {
-            std::fs::create_dir(&self.dest).map_err(|e| BuildError::IoError(e))?;
+        // Leftovers from a build that was interrupted
+        if self.dest.exists() {
+            std::fs::remove_dir_all(&self.dest).map_err(BuildError::IoError)?;
         }
+        std::fs::create_dir_all(&self.dest).map_err(BuildError::IoError)?;
+
+        let mut markdown = Vec::new();
+        for entry in WalkDir::new(&self.root).sort_by_file_name() {
+            // A missing source would otherwise build an empty site
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    let path = e.path().unwrap_or(&self.root).to_owned();
+                    self.errors.push(BuildError::Read(path, e.into()));
+                    continue;
+                }
+            };
 
-        for entry in WalkDir::new(&self.root).into_iter().filter_map(|e| e.ok()) {
             if entry.file_type().is_dir() {
                 let path = self.new_path(entry.path());
                 if !path.exists() {
-                    std::fs::create_dir(path).map_err(|e| BuildError::IoError(e))?;
+                    std::fs::create_dir(path).map_err(BuildError::IoError)?;
                 }
+            } else if entry.path().extension().is_some_and(|ext| ext == "md") {
+                markdown.push(entry.into_path());
             } else {
-                let _ = self.process_file(entry.path());
+                self.process_static(entry.path());
+            }
+        }
+
+        // Pages don't depend on each other until they're merged, so they're rendered in
+        // parallel, then merged in path order for the build to be deterministic
+        let rendered: Vec<_> = markdown
+            .par_iter()
+            .map(|path| self.render_md(path))
+            .collect();
+
+        for (path, rendered) in markdown.iter().zip(rendered) {
+            match rendered {
+                Ok(rendered) => self.merge_md(rendered),
+                Err(e) => {
+                    let mut page = self.dest_key(&self.new_path(path));
+                    page.set_extension("html");
+                    self.fail(&self.dest.join(self.config.output_path(&page)), e);
+                }
+            }
+        }
+
+        self.generate_category_indexes()?;
+        self.generate_tag_pages()
+    }
+
+    /// Generates the index page of every category, listing all of its pages from newest to
+    /// oldest below the category's hand-written intro (`<dir>/<dir>.md`), if it has one.
+    fn generate_category_indexes(&mut self) -> Result<(), BuildError> {
+        // The top level index is a regular page
+        let categories: Vec<Category> = self
+            .config
+            .categories
+            .iter()
+            .filter(|category| !category.dir.is_empty())
+            .cloned()
+            .collect();
+
+        for category in categories.iter() {
+            let mut pages = self.categories.remove(&category.dir).unwrap_or_default();
+            let intro = self.category_intros.remove(&category.dir);
+
+            if pages.is_empty() && intro.is_none() {
+                continue;
+            }
+
+            sort_by_date(&mut pages);
+
+            let dir = self.dest.join(&category.dir);
+            if !dir.exists() {
+                std::fs::create_dir(&dir).map_err(BuildError::IoError)?;
+            }
+
+            let path = category.index_path();
+            let output_path = self.config.output_path(&path);
+            let prefix = root_prefix(&output_path);
+            let to = self.dest.join(output_path);
+
+            let metadata = Metadata {
+                title: category.name.clone(),
+                ..Default::default()
+            };
+            let (from, intro) = match intro {
+                Some((from, page)) => (from, to_html(&page, &self.config, &prefix)),
+                None => (self.root.join(&category.dir), RenderedMarkdown::default()),
+            };
+
+            let content = intro.html + &format_page_list(&pages, &prefix, &self.config);
+            match self.templates.render(
+                &self.config,
+                &path,
+                &metadata,
+                Some(category),
+                &prefix,
+                &content,
+            ) {
+                Ok(html) => self.insert_page(to, from, html, &intro.links),
+                Err(e) => self.fail(&to, e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Generates a page for every tag listing the pages which use it, plus an index of all tags.
+    fn generate_tag_pages(&mut self) -> Result<(), BuildError> {
+        if self.tags.is_empty() {
+            return Ok(());
+        }
+
+        let tags_dir = self.dest.join("tags");
+        if !tags_dir.exists() {
+            std::fs::create_dir(&tags_dir).map_err(BuildError::IoError)?;
+        }
+
+        let mut generated = Vec::with_capacity(self.tags.len() + 1);
+
+        for (slug, tag) in self.tags.iter_mut() {
+            tag.sort_pages();
+
+            let path = PathBuf::from(format!("tags/{slug}.html"));
+            let output_path = self.config.output_path(&path);
+            let prefix = root_prefix(&output_path);
+            let metadata = Metadata {
+                title: format!("Tagged: {}", tag.name),
+                ..Default::default()
+            };
+            let content = format_tag_page(tag, slug, &prefix, &self.config);
+            let html =
+                self.templates
+                    .render(&self.config, &path, &metadata, None, &prefix, &content);
+
+            generated.push((self.dest.join(output_path), html));
+        }
+
+        let path = PathBuf::from("tags/index.html");
+        let prefix = root_prefix(&path);
+        let metadata = Metadata {
+            title: "Tags".to_owned(),
+            ..Default::default()
+        };
+        let content = format_tag_cloud(&self.tags, &prefix, &self.config);
+        let html = self
+            .templates
+            .render(&self.config, &path, &metadata, None, &prefix, &content);
+        generated.push((self.dest.join(path), html));
+
+        for (path, html) in generated {
+            match html {
+                Ok(html) => self.insert_page(path, self.root.clone(), html, &[]),
+                Err(e) => self.fail(&path, e),
             }
         }
+
         Ok(())
     }
 
-    /// Writes all the changes to the filesystem.
+    /// Records that the page at `path` couldn't be generated because of `e`.
+    fn fail(&mut self, path: &Path, e: BuildError) {
+        self.failed.insert(self.dest_key(path));
+        self.errors.push(e);
+    }
+
+    fn insert_page(&mut self, to: PathBuf, from: PathBuf, html: String, links: &[MarkdownLink]) {
+        self.process_links(&html, &to, &from, links);
+        // Ids come from the final html, templates and raw html in the markdown have some too
+        let ids = extract_ids(&html);
+
+        self.pages.insert(
+            self.dest_key(&to),
+            GeneratedHtml {
+                to,
+                from,
+                content: html,
+                ids,
+            },
+        );
+    }
+
+    /// Writes all the changes to the staging directory, and only once every page, asset and
+    /// feed has been written swaps it in place of the output directory.
     pub fn commit_build(&mut self) -> Result<(), BuildError> {
-        println!("Commiting changes");
-        self.publish_rss().unwrap();
+        info!("Commiting changes");
+        self.publish_feeds()?;
 
-        self.pages.iter().for_each(|(_path, content)| {
-            std::fs::write(&content.to, &content.content).unwrap();
-        });
+        for content in self.pages.values() {
+            // Pretty urls give pages a directory of their own
+            if let Some(dir) = content.to.parent() {
+                std::fs::create_dir_all(dir).map_err(BuildError::IoError)?;
+            }
+            std::fs::write(&content.to, &content.content).map_err(BuildError::IoError)?;
+        }
 
-        self.assets.iter().for_each(|(_path, asset)| {
-            std::fs::copy(&asset.from, &asset.to).unwrap();
-        });
+        for (key, asset) in self.assets.iter() {
+            // Linking the previous build's copy is much cheaper than copying big assets again
+            let unchanged = self
+                .previous
+                .outputs
+                .get(key)
+                .is_some_and(|entry| entry.input == asset.input);
+            if !unchanged || std::fs::hard_link(self.output.join(key), &asset.to).is_err() {
+                debug!("Copying {}", asset.from.display());
+                std::fs::copy(&asset.from, &asset.to)
+                    .map_err(|e| BuildError::Read(asset.from.clone(), e))?;
+            }
+
+            self.manifest.outputs.insert(
+                key.clone(),
+                ManifestEntry {
+                    input: asset.input.clone(),
+                    ..Default::default()
+                },
+            );
+        }
+
+        // Until the new one is saved, the output doesn't match the previous manifest anymore
+        remove_manifest(&self.output)?;
+        self.swap_output()?;
+
+        self.manifest
+            .save(&manifest_file(&self.output))
+            .map_err(BuildError::IoError)
+    }
+
+    /// Replaces the output directory with the staging one, keeping the previous output around
+    /// until the new one is in place.
+    fn swap_output(&self) -> Result<(), BuildError> {
+        let previous = sibling_dir(&self.output, "old");
+        if previous.exists() {
+            std::fs::remove_dir_all(&previous).map_err(BuildError::IoError)?;
+        }
+
+        let had_output = self.output.exists();
+        if had_output {
+            std::fs::rename(&self.output, &previous).map_err(BuildError::IoError)?;
+        }
+
+        if let Err(e) = std::fs::rename(&self.dest, &self.output) {
+            if had_output {
+                let _ = std::fs::rename(&previous, &self.output);
+            }
+            return Err(BuildError::IoError(e));
+        }
+
+        if had_output {
+            std::fs::remove_dir_all(&previous).map_err(BuildError::IoError)?;
+        }
 
         Ok(())
     }
 
-    pub fn publish_rss(self: &mut Self) -> Result<(), BuildError> {
-        let channel = self.rss_feed.build();
-        let dest = self.dest.join(Path::new("rss.xml"));
-        let file = File::create(dest).map_err(|e| BuildError::IoError(e))?;
-        channel.pretty_write_to(file, b' ', 2).unwrap();
+    /// Throws away a failed build, leaving the output directory untouched.
+    pub fn discard_build(&self) {
+        if self.dest.exists() {
+            let _ = std::fs::remove_dir_all(&self.dest);
+        }
+    }
+
+    /// Writes every feed of the site.
+    pub fn publish_feeds(&mut self) -> Result<(), BuildError> {
+        for (path, _, feed) in self.feeds()? {
+            std::fs::write(self.dest.join(path), feed).map_err(BuildError::IoError)?;
+        }
         Ok(())
     }
 
+    /// The feed of posts in every format the config asks for, along with an rss feed per tag,
+    /// keyed by their path relative to the output directory.
+    fn feeds(&self) -> Result<Vec<(PathBuf, FeedFormat, Vec<u8>)>, BuildError> {
+        let mut feeds = Vec::new();
+
+        let channel = new_rss(&self.config, feed_items(&self.feed, &self.config));
+        for &format in self.config.feeds.iter() {
+            let feed = format
+                .write(&channel, &self.config)
+                .map_err(BuildError::IoError)?;
+            feeds.push((PathBuf::from(format.file_name()), format, feed));
+        }
+
+        if !self.config.feeds.contains(&FeedFormat::Rss) {
+            return Ok(feeds);
+        }
+
+        // One feed per tag, with the same channel metadata as the main one
+        for (slug, tag) in self.tags.iter() {
+            let mut tag_channel = new_rss(&self.config, feed_items(&tag.pages, &self.config));
+            tag_channel.set_title(format!("{} - {}", channel.title(), tag.name));
+            tag_channel.set_link(
+                self.config
+                    .page_url(Path::new(&format!("tags/{slug}.html"))),
+            );
+
+            let feed = FeedFormat::Rss
+                .write(&tag_channel, &self.config)
+                .map_err(BuildError::IoError)?;
+            feeds.push((
+                Path::new("tags").join(format!("{slug}.xml")),
+                FeedFormat::Rss,
+                feed,
+            ));
+        }
+
+        Ok(feeds)
+    }
+
+    /// Checks every feed against the rules of its format, without publishing them.
+    pub fn validate_feeds(&self) -> Result<(), BuildError> {
+        info!("Validating feeds");
+
+        let mut problems = Vec::new();
+        for (path, format, feed) in self.feeds()? {
+            let file = self.output.join(path);
+            problems.extend(validate_feed(
+                format,
+                &file,
+                &String::from_utf8_lossy(&feed),
+            ));
+        }
+
+        if !problems.is_empty() {
+            Err(BuildError::InvalidFeed(problems))
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn validate_internal_links(&self) -> Result<(), BuildError> {
-        println!("Validating internal links");
+        info!("Validating internal links");
 
         let mut invalid_links = InvalidLinks(Vec::new());
 
         let data = &self.relative_links;
         for item in data.iter() {
-            if let Err(_) = self.heading_link_exists(&item.0) {
-                invalid_links.0.push(item.0.clone());
+            if self.config.links.is_allowed(&item.0.link) {
+                continue;
+            }
+            if let Err(suggestion) = self.heading_link_exists(&item.0) {
+                invalid_links.0.push(InvalidLink {
+                    link: item.0.clone(),
+                    suggestion,
+                });
             }
         }
 
@@ -133,17 +536,22 @@ impl Site {
         }
     }
 
-    fn process_file(&mut self, entry: &Path) -> Result<(), BuildError> {
-        if entry
-            .extension()
-            .unwrap_or_default()
-            .to_str()
-            .unwrap_or_default()
-            == "md"
-        {
-            self.process_md(entry)
+    pub fn validate_external_links(
+        &self,
+        validator: &ExternalLinkValidator,
+    ) -> Result<(), BuildError> {
+        info!("Validating external links");
+
+        let links = self.url_links.as_deref().unwrap_or_default();
+        let broken = validator.validate(links);
+
+        if let Err(e) = validator.save_cache() {
+            warning!("Couldn't save external link cache: {:?}", e);
+        }
+
+        if !broken.is_empty() {
+            Err(BuildError::BrokenUrls(broken))
         } else {
-            self.process_static(entry);
             Ok(())
         }
     }
@@ -151,137 +559,289 @@ impl Site {
     fn process_static(&mut self, old_path: &Path) {
         let new_path = self.new_path(old_path);
 
+        // Hashed now so that unreadable assets are reported along with every other error
+        let input = match std::fs::read(old_path) {
+            Ok(contents) => hash(&contents),
+            Err(e) => {
+                self.fail(&new_path, BuildError::Read(old_path.to_owned(), e));
+                return;
+            }
+        };
+
         self.assets.insert(
-            canonical(&new_path),
+            self.dest_key(&new_path),
             AssetFile {
                 to: new_path,
                 from: old_path.to_owned(),
+                input,
             },
         );
     }
 
-    fn process_md(&mut self, old_path: &Path) -> Result<(), BuildError> {
-        let mut new_path = self.new_path(old_path);
-        new_path.set_extension("html");
-        let page = Page::new(old_path, new_path.strip_prefix(&self.dest).unwrap());
+    /// Renders the markdown file at `old_path` into its final html, without touching the site.
+    fn render_md(&self, old_path: &Path) -> Result<RenderedPage, BuildError> {
+        let mut path = self.dest_key(&self.new_path(old_path));
+        path.set_extension("html");
+        let page = Page::new(old_path, &path, &self.config)?;
 
-        if page.metadata.draft {
-            return Ok(());
+        if page.metadata.draft && !self.config.drafts {
+            return Ok(RenderedPage::Draft);
         }
 
-        if page.is_post() {
-            self.rss_feed.item(new_item(&page));
-        };
-
-        let mut html_content = format_metadata(&page.metadata);
+        // Category indexes are generated once all pages are known
+        if let Some(category) = page.category.as_ref()
+            && page.kind == PageKind::Index
+            && !category.dir.is_empty()
+        {
+            return Ok(RenderedPage::CategoryIntro(old_path.to_owned(), page));
+        }
 
-        let content = to_html(&page);
+        let output_path = self.config.output_path(&page.path);
+        let prefix = &root_prefix(&output_path);
+        let new_path = self.dest.join(output_path);
 
-        html_content.push_str(&content);
+        let input = hash(page.content.as_bytes());
+        if let Some((html, entry)) = self.reuse(&new_path, &input) {
+            debug!("Reusing {}", old_path.display());
+            let content = match &entry.feed {
+                Some(feed) => RenderedMarkdown {
+                    html: feed.html.clone(),
+                    summary: feed.summary.clone(),
+                    ..Default::default()
+                },
+                None => RenderedMarkdown::default(),
+            };
+            let item = Box::new(new_item(&page, &self.config, &content));
+            return Ok(RenderedPage::Page {
+                from: old_path.to_owned(),
+                to: new_path,
+                page,
+                html,
+                entry,
+                item,
+            });
+        }
 
-        let dest_string = new_path.to_str().unwrap_or_default();
-        let root_string = self.dest.to_str().unwrap_or_default();
+        debug!("Rendering {}", old_path.display());
+        let content = to_html(&page, &self.config, prefix);
+        let item = Box::new(new_item(&page, &self.config, &content));
 
-        let (_, relative) = dest_string.split_once(root_string).unwrap_or_default();
+        let html = self.templates.render(
+            &self.config,
+            &page.path,
+            &page.metadata,
+            page.category.as_ref(),
+            prefix,
+            &content.html,
+        )?;
 
-        let depth = relative.chars().filter(|c| *c == '/').count() - 1;
-        let prefix = if depth == 0 { "" } else { "../" };
-        let html_header = format_header(&page.metadata.title, prefix);
-        let html_navbar = format_navbar(prefix, page.category);
-        let html_footer = format_footer();
+        // Kept for the feeds of the next build, if the page is reused
+        let feed = page.in_feeds().then_some(FeedContent {
+            html: content.html,
+            summary: content.summary,
+        });
 
-        let html = html_header + &html_navbar + &html_content + &html_footer;
+        Ok(RenderedPage::Page {
+            from: old_path.to_owned(),
+            to: new_path,
+            page,
+            html,
+            entry: ManifestEntry {
+                input,
+                links: content.links,
+                feed,
+            },
+            item,
+        })
+    }
 
-        self.process_links(&html, &new_path);
+    /// Html of the page at `path` from the previous build, if it was rendered from the same
+    /// `input` the same way this build would.
+    fn reuse(&self, path: &Path, input: &str) -> Option<(String, ManifestEntry)> {
+        if !self.manifest.renders_like(&self.previous) {
+            return None;
+        }
 
-        self.pages.insert(
-            canonical(&new_path),
-            GeneratedHtml {
-                to: new_path.to_owned(),
-                from: old_path.to_owned(),
-                content: html,
-            },
-        );
+        let key = self.dest_key(path);
+        let entry = self.previous.outputs.get(&key)?;
+        if entry.input != input {
+            return None;
+        }
 
-        Ok(())
+        let html = std::fs::read_to_string(self.output.join(&key)).ok()?;
+        Some((html, entry.clone()))
     }
 
-    fn process_links(&mut self, source: &str, path: &Path) {
-        let href = "href=";
+    /// Adds a rendered page to the site, and to the categories, tags and feed it belongs to.
+    fn merge_md(&mut self, rendered: RenderedPage) {
+        let (from, to, page, html, entry, item) = match rendered {
+            RenderedPage::Draft => return,
+            RenderedPage::CategoryIntro(from, page) => {
+                if let Some(category) = page.category.as_ref() {
+                    self.category_intros
+                        .insert(category.dir.clone(), (from, page));
+                }
+                return;
+            }
+            RenderedPage::Page {
+                from,
+                to,
+                page,
+                html,
+                entry,
+                item,
+            } => (from, to, page, html, entry, item),
+        };
 
-        let mut source = source;
+        let summary = PageSummary::new(&page, *item);
 
-        while let Some(link_start) = source.find(href) {
-            source = &source[link_start + href.len()..];
+        if let Some(category) = page.category.as_ref()
+            && page.kind == PageKind::Article
+        {
+            self.categories
+                .entry(category.dir.clone())
+                .or_default()
+                .push(summary.clone());
+        }
 
-            let quote = source.chars().nth(0).unwrap();
-            source = &source[1..];
+        if page.in_rss() {
+            self.feed.push(summary.clone());
+        };
 
-            if let Some(end) = source.find(quote) {
-                let link = &source[..end];
+        for name in page.metadata.tags.iter() {
+            let slug = tag_slug(name);
+            if slug.is_empty() {
+                continue;
+            }
+            let tag = self.tags.entry(slug).or_insert_with(|| Tag::new(name));
+
+            // Only the same tag written in another case may share its page
+            if tag.name.to_lowercase() != name.to_lowercase() {
+                let message = format!(
+                    "tag `{name}` would share the page of tag `{}`, rename one of them",
+                    tag.name
+                );
+                let e = FrontMatterError::new(&from, page.key_line("tags"), message);
+                self.fail(&to, BuildError::FrontMatter(e));
+                continue;
+            }
+            tag.pages.push(summary.clone());
+        }
+
+        self.insert_page(to.clone(), from, html, &entry.links);
+        self.manifest.outputs.insert(self.dest_key(&to), entry);
+    }
+
+    /// Records every link of the generated page at `path`, pointing each one back at where it
+    /// was written in `source` (the page's markdown) when it can be found among `markdown`.
+    fn process_links(&mut self, html: &str, path: &Path, source: &Path, markdown: &[MarkdownLink]) {
+        let mut unmatched: Vec<&MarkdownLink> = markdown.iter().collect();
+
+        for url in extract_links(html) {
+            let position = unmatched
+                .iter()
+                .position(|written| written.url == url)
+                .map(|i| unmatched.remove(i))
+                .map(|written| LinkPosition {
+                    file: source.to_path_buf(),
+                    line: written.line,
+                    column: written.column,
+                    length: written.length,
+                });
 
-                match link {
-                    // TODO: Do we still need to external links?
-                    external if link.contains("http") => {
-                        let link = UrlLink(Link {
-                            link: external.to_owned(),
-                            file: path.to_path_buf(),
-                        });
+            let link = Link {
+                link: url,
+                file: path.to_path_buf(),
+                position,
+            };
 
-                        match self.url_links.as_mut() {
-                            None => self.url_links = Some(vec![link]),
-                            Some(links) => links.push(link),
-                        }
-                    }
-                    internal => {
-                        self.relative_links.push(RelativeLink(Link {
-                            link: internal.to_owned(),
-                            file: path.to_path_buf(),
-                        }));
-                    }
+            // Absolute links to this site are checked against this build, which the live
+            // site might not have yet
+            if is_external(&link.link) && self.config.site_link(&link.link).is_none() {
+                let link = UrlLink(link);
+                match self.url_links.as_mut() {
+                    None => self.url_links = Some(vec![link]),
+                    Some(links) => links.push(link),
                 }
+            } else {
+                self.relative_links.push(RelativeLink(link));
             }
         }
     }
 
-    fn heading_link_exists(self: &Self, link: &Link) -> Result<(), ()> {
-        // TODO deal with full paths everywhere and only convert to relative when necessary?
-        let (file_path, heading) = match link.link.split_once("#") {
-            // Link is only path
-            None => (Path::new(&link.link), ""),
+    /// Checks that the page (or asset) `link` points to exists, and that its fragment, if any,
+    /// is the id of an element inside the page. If only the fragment is wrong, returns the
+    /// closest existing id as a suggestion.
+    fn heading_link_exists(&self, link: &Link) -> Result<(), Option<String>> {
+        let target = self
+            .config
+            .site_link(&link.link)
+            .unwrap_or_else(|| link.link.clone());
+        let (file, heading) = target.split_once('#').unwrap_or((&target, ""));
+        // Ids with non-ascii characters are linked to percent-encoded
+        let heading = &percent_decode(heading).unwrap_or_else(|| heading.to_owned());
+        let file = file.split('?').next().unwrap_or_default();
+        let decoded = percent_decode(file).unwrap_or_else(|| file.to_owned());
+
+        let linker = self.dest_key(&link.file);
+        let mut linkee = if file.is_empty() {
             // Link is only heading (file is the current one)
-            Some((file, heading)) if file.is_empty() => {
-                let path = canonical(Path::new(&link.file));
-                if let Some(page) = self.pages.get(&path) {
-                    return match page.content.find(heading) {
-                        None => Err(()),
-                        Some(_) => Ok(()),
-                    };
-                } else {
-                    return Err(());
-                }
-            }
-            // Link is path + heading
-            Some((file, link)) => (Path::new(file), link),
+            linker
+        } else if let Some(decoded) = decoded.strip_prefix('/') {
+            // Link is relative to the site's root
+            normalize(Path::new(decoded))
+        } else {
+            normalize(&linker.parent().unwrap_or(Path::new("")).join(&decoded))
         };
 
-        let path_to_linker = self.root.join(link.file.parent().unwrap());
-        let path_to_linkee = path_to_linker.join(file_path);
-        let abs_path = canonical(&path_to_linkee);
+        // Links to a directory get its index, like pretty urls do
+        if decoded.ends_with('/') || decoded == "." || decoded == ".." {
+            linkee.push("index.html");
+        }
 
-        if let Some(page) = self.pages.get(&abs_path) {
-            match page.content.find(heading) {
-                None => Err(()),
-                Some(_) => Ok(()),
+        if let Some(page) = self.pages.get(&linkee) {
+            if heading.is_empty() || page.ids.contains(heading) {
+                Ok(())
+            } else {
+                Err(closest_id(&page.ids, heading).map(|id| format!("{file}#{id}")))
             }
-        } else if let Some(_) = self.assets.get(&abs_path) {
-            // Can't have internal links to assets
-            assert!(heading.is_empty());
+        } else if (self.assets.contains_key(&linkee) || self.is_feed(&linkee)) && heading.is_empty()
+        {
+            // Can't have heading links to assets
+            Ok(())
+        } else if self.failed.contains(&linkee) {
+            // Already reported, its ids aren't known
             Ok(())
         } else {
-            println!("{abs_path:?} not found");
-            Err(())
+            Err(None)
+        }
+    }
+
+    /// Whether one of the feeds, which are only written once the build is committed, is
+    /// published at `path` (relative to dest).
+    fn is_feed(&self, path: &Path) -> bool {
+        if self
+            .config
+            .feeds
+            .iter()
+            .any(|format| path == Path::new(format.file_name()))
+        {
+            return true;
         }
+
+        // Tags only get an rss feed
+        self.config.feeds.contains(&FeedFormat::Rss)
+            && path.parent() == Some(Path::new("tags"))
+            && path.extension().is_some_and(|ext| ext == "xml")
+            && path
+                .file_stem()
+                .is_some_and(|slug| self.tags.contains_key(&*slug.to_string_lossy()))
+    }
+
+    /// Key of `path` (inside dest) in the `pages` and `assets` maps: its normalized path
+    /// relative to dest.
+    fn dest_key(&self, path: &Path) -> PathBuf {
+        normalize(path.strip_prefix(&self.dest).unwrap_or(path))
     }
 
     fn new_path(&self, path: &Path) -> PathBuf {
@@ -290,15 +850,118 @@ impl Site {
     }
 }
 
-fn canonical(path: &Path) -> PathBuf {
-    if !path.exists() {
-        let _ = std::fs::write(path, "");
-    };
+/// Relative path from the directory of `path` (relative to the output directory) to the root
+/// of the site, e.g. `../` for `posts/foo.html`.
+fn root_prefix(path: &Path) -> String {
+    "../".repeat(path.components().count().saturating_sub(1))
+}
+
+/// Reports the problems of `e` without failing the build.
+fn warn(e: &BuildError) {
+    for diagnostic in e.diagnostics() {
+        warning!("{}\n", diagnostic.with_severity(Severity::Warning));
+    }
+}
+
+/// Directory next to `output` where builds are staged before replacing it.
+fn staging_dir(output: &Path) -> PathBuf {
+    sibling_dir(output, "staging")
+}
+
+/// Where the manifest of the build in `output` is kept, outside of it so it isn't published.
+fn manifest_file(output: &Path) -> PathBuf {
+    sibling_dir(output, "manifest.json")
+}
+
+fn remove_manifest(output: &Path) -> Result<(), BuildError> {
+    match std::fs::remove_file(manifest_file(output)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(BuildError::IoError(e)),
+        _ => Ok(()),
+    }
+}
+
+/// Hidden directory (or file) next to `dir`, e.g. `.output.staging` for `output`.
+fn sibling_dir(dir: &Path, suffix: &str) -> PathBuf {
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "output".to_owned());
+
+    dir.with_file_name(format!(".{name}.{suffix}"))
+}
+
+/// Lexically resolves `.` and `..` components, without touching the filesystem. Leading `..`
+/// components which can't be resolved are kept.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                // There's nothing above the root
+                Some(Component::RootDir | Component::Prefix(_)) => (),
+                _ => normalized.push(".."),
+            },
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// The id closest to `target` if it's close enough to likely be a typo: differing only in case, or
+/// by at most a third of its characters.
+fn closest_id<'a>(ids: &'a BTreeSet<String>, target: &str) -> Option<&'a str> {
+    let target_len = target.chars().count();
+    let max_distance = (target_len / 3).max(1);
+
+    ids.iter()
+        .map(|id| {
+            let distance = if id.to_lowercase() == target.to_lowercase() {
+                0
+            } else {
+                edit_distance(id, target)
+            };
+            (distance, id)
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, id)| id.as_str())
+}
+
+/// Levenshtein distance between `a` and `b`, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
 
-    path.canonicalize().unwrap()
+/// Id given to a heading's element, which is also the fragment used to link to it.
+fn heading_id(content: &str) -> String {
+    content.replace(" ", "-")
 }
 
-struct Heading;
+/// Renders headings with linkable ids.
+#[derive(Default)]
+struct Heading {
+    prefix: String,          // Path to the site's root, for the link icon
+    toc: Mutex<Vec<String>>, // Ids of the `h2`s, which make up the table of contents
+}
 
 impl adapters::HeadingAdapter for Heading {
     fn enter(
@@ -322,12 +985,12 @@ impl adapters::HeadingAdapter for Heading {
             .unwrap();
         }
 
-        write!(
-            output,
-            "<h{} id=\"{}\">",
-            level,
-            heading.content.replace(" ", "-")
-        )
+        let id = heading_id(&heading.content);
+        write!(output, "<h{} id=\"{}\">", level, id)?;
+        if level == 2 {
+            self.toc.lock().unwrap().push(id);
+        }
+        Ok(())
     }
 
     fn exit(
@@ -345,12 +1008,13 @@ impl adapters::HeadingAdapter for Heading {
             write!(
                 output,
                 "<a class=\"header-link\" href=\"#{}\">\
-                <img src=\"../assets/link.svg\" class=\"link-icon\" alt=\"Link icon\">\
+                <img src=\"{}assets/link.svg\" class=\"link-icon\" alt=\"Link icon\">\
                 </a>\
                 </h{}>\
                 <div class=\"small-break\"></div>\
                 </div>",
-                heading.content.replace(" ", "-"),
+                heading_id(&heading.content),
+                self.prefix,
                 level,
             )
         } else {
@@ -359,16 +1023,36 @@ impl adapters::HeadingAdapter for Heading {
     }
 }
 
-pub fn to_html(page: &Page) -> String {
+/// Syntect's syntaxes and themes are slow to load, so every render shares them.
+fn syntax_adapter() -> &'static SyntectAdapter {
+    static ADAPTER: OnceLock<SyntectAdapter> = OnceLock::new();
+    ADAPTER.get_or_init(|| SyntectAdapter::new(Some("base16-mocha.dark")))
+}
+
+/// Html rendered from a page's markdown.
+#[derive(Debug, Clone, Default)]
+pub struct RenderedMarkdown {
+    pub html: String,
+    /// Text of the first paragraph, which feeds use when the page has no summary.
+    pub summary: Option<String>,
+    /// Links written in the markdown, in document order.
+    pub links: Vec<MarkdownLink>,
+}
+
+/// Renders `page`'s markdown, `prefix` being the path from the page's directory to the root.
+pub fn to_html(page: &Page, config: &Config, prefix: &str) -> RenderedMarkdown {
     let mut options = Options::default();
-    options.extension.front_matter_delimiter = Some("+++".to_owned());
+    options.extension.front_matter_delimiter =
+        FrontMatterFormat::detect(&page.content).map(|format| format.delimiter().to_owned());
     options.extension.footnotes = true;
 
-    let heading_adapter = Heading;
-    let syntax_adapter = plugins::syntect::SyntectAdapter::new(Some("base16-mocha.dark"));
+    let heading_adapter = Heading {
+        prefix: prefix.to_owned(),
+        ..Default::default()
+    };
     let render_plugin = RenderPluginsBuilder::default()
         .heading_adapter(Some(&heading_adapter))
-        .codefence_syntax_highlighter(Some(&syntax_adapter))
+        .codefence_syntax_highlighter(Some(syntax_adapter()))
         .build()
         .unwrap();
 
@@ -377,10 +1061,130 @@ pub fn to_html(page: &Page) -> String {
         .build()
         .unwrap();
 
-    let html = markdown_to_html_with_plugins(&page.content, &options, &plugin);
+    let arena = Arena::new();
+    let root = parse_document(&arena, &page.content, &options);
+
+    // Comrak counts lines from the end of the front matter
+    let offset = root
+        .first_child()
+        .and_then(|node| match &node.data.borrow().value {
+            NodeValue::FrontMatter(front_matter) => Some(front_matter.matches('\n').count()),
+            _ => None,
+        })
+        .unwrap_or(0);
+
+    let summary = root
+        .children()
+        .filter(|node| matches!(node.data.borrow().value, NodeValue::Paragraph))
+        .map(plain_text)
+        .find(|text| !text.is_empty());
+
+    let mut links = Vec::new();
+    for node in root.descendants() {
+        if let NodeValue::Link(link) | NodeValue::Image(link) = &mut node.data.borrow_mut().value
+            && let Some(url) = pretty_link(config, &page.path, &link.url)
+        {
+            link.url = url;
+        }
+
+        let data = node.data.borrow();
+        let (start, end) = (data.sourcepos.start, data.sourcepos.end);
+        let (line, column) = (start.line + offset, start.column);
+        let length = if end.line == start.line {
+            end.column + 1 - start.column
+        } else {
+            0
+        };
+        match &data.value {
+            NodeValue::Link(link) | NodeValue::Image(link) => links.push(MarkdownLink {
+                url: link.url.clone(),
+                line,
+                column,
+                length,
+            }),
+            NodeValue::HtmlBlock(NodeHtmlBlock { literal, .. })
+            | NodeValue::HtmlInline(literal) => {
+                links.extend(extract_links(literal).into_iter().map(|url| MarkdownLink {
+                    url,
+                    line,
+                    column,
+                    length,
+                }));
+            }
+            _ => (),
+        }
+    }
+
+    let mut html = Vec::new();
+    format_html_with_plugins(root, &options, &mut html, &plugin).unwrap();
+    let html = String::from_utf8(html).unwrap();
 
-    match (page.category, page.kind) {
-        (Category::Post, PageKind::Article) => table_of_contents(html),
-        (_, _) => html,
+    let html = if page.has_toc() {
+        table_of_contents(html, &heading_adapter.toc.into_inner().unwrap())
+    } else {
+        html
+    };
+
+    RenderedMarkdown {
+        html,
+        summary,
+        links,
+    }
+}
+
+/// `link`, written in the markdown of the page at `path`, pointing at where its target is
+/// published with pretty urls: pages get a directory of their own, so relative links need one
+/// more `../`, and links to pages drop their `.html`. `None` if the link doesn't need to change.
+fn pretty_link(config: &Config, path: &Path, link: &str) -> Option<String> {
+    let has_scheme = link
+        .split_once(':')
+        .is_some_and(|(scheme, _)| !scheme.contains(['/', '?', '#']));
+    // Empty links, fragments and queries stay on the same page, wherever it is
+    if !config.pretty_urls
+        || link.is_empty()
+        || has_scheme
+        || link.starts_with(['#', '?'])
+        || link.starts_with("//")
+    {
+        return None;
+    }
+
+    let (target, suffix) = link.split_at(link.find(['?', '#']).unwrap_or(link.len()));
+    let decoded = percent_decode(target)?;
+    let (prefix, target) = match decoded.strip_prefix('/') {
+        Some(target) => ("/".to_owned(), normalize(Path::new(target))),
+        None => (
+            root_prefix(&config.output_path(path)),
+            normalize(&path.parent().unwrap_or(Path::new("")).join(&decoded)),
+        ),
+    };
+    if target.starts_with("..") {
+        // Outside of the site, there's nothing to point at
+        return None;
+    }
+
+    let mut href = if target.extension().is_some_and(|ext| ext == "html") {
+        config.page_href(&target, &prefix)
+    } else {
+        format!("{prefix}{}", percent_encode(&target.to_string_lossy()))
+    };
+    if decoded.ends_with('/') && !href.ends_with('/') {
+        href.push('/');
+    }
+
+    Some(href + suffix)
+}
+
+/// Text of `node` without any formatting, on a single line.
+fn plain_text<'a>(node: &'a AstNode<'a>) -> String {
+    let mut text = String::new();
+    for node in node.descendants() {
+        match &node.data.borrow().value {
+            NodeValue::Text(literal) => text.push_str(literal),
+            NodeValue::Code(code) => text.push_str(&code.literal),
+            NodeValue::SoftBreak | NodeValue::LineBreak => text.push(' '),
+            _ => (),
+        }
     }
+    text.trim().to_owned()
 }
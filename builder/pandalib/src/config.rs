@@ -0,0 +1,200 @@
+use crate::{BuildError, ConfigError, FeedFormat, all_feed_formats};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Site wide settings, read from `site.toml`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Absolute url the site is served from, e.g. "https://eduardorittner.github.io".
+    pub base_url: String,
+    pub title: String,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default = "default_language")]
+    pub language: String,
+    #[serde(default)]
+    pub description: String,
+    /// Directory with the site's sources, relative to the config file.
+    #[serde(default = "default_source")]
+    pub source: PathBuf,
+    /// Directory the site is built into, relative to the config file.
+    #[serde(default = "default_output")]
+    pub output: PathBuf,
+    /// Directory with the page layouts, relative to the config file.
+    #[serde(default = "default_templates")]
+    pub templates: PathBuf,
+    #[serde(default)]
+    pub categories: Vec<Category>,
+    #[serde(default)]
+    pub links: LinksConfig,
+    #[serde(default)]
+    pub external_links: ExternalLinksConfig,
+    /// Render draft pages too, to preview them.
+    #[serde(default)]
+    pub drafts: bool,
+    /// Formats the feed of posts is published in, every one by default.
+    #[serde(default = "all_feed_formats")]
+    pub feeds: Vec<FeedFormat>,
+    /// Most items a feed holds, the newest ones. Feeds hold every page by default.
+    #[serde(default)]
+    pub feed_limit: Option<usize>,
+    /// Write pages as `foo/index.html` and link to them as `foo/` instead of `foo.html`.
+    /// Relative links written in markdown are rewritten to match, so they keep pointing at the
+    /// same pages and assets.
+    #[serde(default)]
+    pub pretty_urls: bool,
+}
+
+/// How internal links are validated.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LinksConfig {
+    /// Fail the build on invalid links instead of only reporting them.
+    #[serde(default)]
+    pub strict: bool,
+    /// Links which are known to be invalid and shouldn't be reported. An entry ending in `*`
+    /// allows every link starting with the rest of it.
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+/// How external links are checked, when they are.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct ExternalLinksConfig {
+    /// How many requests are made at the same time.
+    pub concurrency: usize,
+    /// Minimum time between two requests to the same host.
+    pub per_host_interval_ms: u64,
+    pub timeout_secs: u64,
+    /// File where results are kept between builds, relative to the config file.
+    pub cache: Option<PathBuf>,
+    /// How long cached results are trusted for.
+    pub ttl_hours: u64,
+}
+
+impl Default for ExternalLinksConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 8,
+            per_host_interval_ms: 1000,
+            timeout_secs: 10,
+            cache: None,
+            ttl_hours: 7 * 24,
+        }
+    }
+}
+
+impl LinksConfig {
+    pub fn is_allowed(&self, link: &str) -> bool {
+        self.allow
+            .iter()
+            .any(|allowed| match allowed.strip_suffix('*') {
+                Some(prefix) => link.starts_with(prefix),
+                None => link == allowed,
+            })
+    }
+}
+
+/// A section of the site, made up of every page inside `dir`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Category {
+    /// Directory relative to the source directory, empty for the top level pages.
+    pub dir: String,
+    /// Name shown in the navbar and as the title of the category's index.
+    pub name: String,
+    /// Position in the navbar, categories without one are left out of it.
+    #[serde(default)]
+    pub navbar: Option<u32>,
+    /// Whether the category's pages go in the rss feed.
+    #[serde(default)]
+    pub rss: bool,
+    /// Whether the category's pages get a table of contents.
+    #[serde(default)]
+    pub toc: bool,
+    /// Template used for the category's pages, defaults to `page.html`.
+    #[serde(default)]
+    pub layout: Option<String>,
+}
+
+impl Category {
+    /// Path of the category's index relative to the output directory, `<dir>/<dir>.html` or
+    /// `index.html` for the top level.
+    pub fn index_path(&self) -> PathBuf {
+        if self.dir.is_empty() {
+            PathBuf::from("index.html")
+        } else {
+            Path::new(&self.dir).join(format!("{}.html", self.dir))
+        }
+    }
+}
+
+fn default_language() -> String {
+    "en-us".to_owned()
+}
+
+fn default_source() -> PathBuf {
+    PathBuf::from("src")
+}
+
+fn default_output() -> PathBuf {
+    PathBuf::from("output")
+}
+
+fn default_templates() -> PathBuf {
+    PathBuf::from("templates")
+}
+
+impl Config {
+    /// Reads the config at `path`, resolving `source` and `output` relative to it.
+    pub fn load(path: &Path) -> Result<Config, BuildError> {
+        let contents = std::fs::read_to_string(path).map_err(BuildError::IoError)?;
+        Config::parse(&contents, path)
+    }
+
+    pub fn parse(contents: &str, path: &Path) -> Result<Config, BuildError> {
+        let mut config: Config = toml::from_str(contents).map_err(|e| {
+            let line = e
+                .span()
+                .map(|span| contents[..span.start].matches('\n').count() + 1)
+                .unwrap_or(1);
+            BuildError::Config(ConfigError::new(path, line, e.message()))
+        })?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        config.source = dir.join(&config.source);
+        config.output = dir.join(&config.output);
+        config.templates = dir.join(&config.templates);
+        config.external_links.cache = config.external_links.cache.map(|cache| dir.join(cache));
+        config.base_url = config.base_url.trim_end_matches('/').to_owned();
+
+        Ok(config)
+    }
+
+    /// Category of the page at `path`, relative to either the source or output directory.
+    pub fn category_of(&self, path: &Path) -> Option<&Category> {
+        let mut components = path.components();
+        let first = components.next()?;
+
+        let dir = if components.next().is_some() {
+            first.as_os_str().to_str()?
+        } else {
+            ""
+        };
+
+        self.categories.iter().find(|category| category.dir == dir)
+    }
+
+    /// Categories shown in the navbar, in order.
+    pub fn navbar(&self) -> Vec<&Category> {
+        let mut navbar: Vec<&Category> = self
+            .categories
+            .iter()
+            .filter(|category| category.navbar.is_some())
+            .collect();
+        navbar.sort_by_key(|category| category.navbar);
+        navbar
+    }
+}
@@ -0,0 +1,124 @@
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found while building, pointing at where it was written.
+///
+/// Displays like rustc does, quoting the offending line of the source file when it can still
+/// be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub file: PathBuf,
+    pub line: usize,   // 1-based, 0 when it concerns the whole file
+    pub column: usize, // 1-based, 0 when it concerns the whole line
+    pub length: usize, // Characters to highlight from `column`
+    pub help: Option<String>,
+    /// The line pointed at, for files which aren't on disk like the feeds of `panda check`.
+    pub snippet: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, file: &Path, line: usize, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            message: message.into(),
+            file: file.to_path_buf(),
+            line,
+            column: 0,
+            length: 0,
+            help: None,
+            snippet: None,
+        }
+    }
+
+    pub fn error(file: &Path, line: usize, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, file, line, message)
+    }
+
+    pub fn warning(file: &Path, line: usize, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, file, line, message)
+    }
+
+    pub fn with_column(mut self, column: usize, length: usize) -> Self {
+        self.column = column;
+        self.length = length;
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    /// Line of the source file the diagnostic points at, if it can still be read.
+    fn source_line(&self) -> Option<String> {
+        if self.snippet.is_some() {
+            return self.snippet.clone();
+        }
+        let line = self.line.checked_sub(1)?;
+        let source = std::fs::read_to_string(&self.file).ok()?;
+        source.lines().nth(line).map(str::to_owned)
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}: {}", self.severity, self.message)?;
+
+        let gutter = " ".repeat(self.line.to_string().len());
+        match (self.line, self.column) {
+            (0, _) => write!(f, "{gutter}--> {}", self.file.display())?,
+            (line, 0) => write!(f, "{gutter}--> {}:{}", self.file.display(), line)?,
+            (line, column) => write!(f, "{gutter}--> {}:{}:{}", self.file.display(), line, column)?,
+        }
+
+        if let Some(source) = self.source_line() {
+            // Columns count bytes, like comrak's
+            let (start, length) = if self.column == 0 {
+                let trimmed = source.trim_start();
+                (
+                    source.len() - trimmed.len(),
+                    trimmed.trim_end().chars().count(),
+                )
+            } else {
+                (self.column - 1, self.length)
+            };
+            let padding: String = source
+                .get(..start)
+                .unwrap_or_default()
+                .chars()
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+
+            write!(f, "\n{gutter} |")?;
+            write!(f, "\n{} | {}", self.line, source)?;
+            write!(f, "\n{gutter} | {}{}", padding, "^".repeat(length.max(1)))?;
+        }
+
+        if let Some(help) = &self.help {
+            write!(f, "\n{gutter} = help: {help}")?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,26 +1,229 @@
-use crate::link::Link;
+use crate::UrlStatus;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::link::{Link, UrlLink};
+use std::path::{Path, PathBuf};
 
-pub struct InvalidLinks(pub Vec<Link>);
+pub struct InvalidLinks(pub Vec<InvalidLink>);
+
+pub struct InvalidLink {
+    pub link: Link,
+    /// An existing link close to the invalid one, when only its fragment is wrong.
+    pub suggestion: Option<String>,
+}
+
+/// Malformed front matter, pointing at the offending line of the source file.
+#[derive(Debug, Clone)]
+pub struct FrontMatterError {
+    pub file: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl FrontMatterError {
+    pub fn new(file: &Path, line: usize, message: impl Into<String>) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for FrontMatterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.file.display(), self.line, self.message)
+    }
+}
+
+/// Invalid `site.toml`, pointing at the offending line.
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    pub file: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl ConfigError {
+    pub fn new(file: &Path, line: usize, message: impl Into<String>) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.file.display(), self.line, self.message)
+    }
+}
+
+/// Template that failed to load or render, pointing at the offending line.
+#[derive(Debug, Clone)]
+pub struct TemplateError {
+    pub file: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl TemplateError {
+    pub fn new(file: &Path, line: usize, message: impl Into<String>) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.file.display(), self.line, self.message)
+    }
+}
+
+impl From<&FrontMatterError> for Diagnostic {
+    fn from(e: &FrontMatterError) -> Self {
+        Diagnostic::error(
+            &e.file,
+            e.line,
+            format!("invalid front matter: {}", e.message),
+        )
+    }
+}
+
+impl From<&ConfigError> for Diagnostic {
+    fn from(e: &ConfigError) -> Self {
+        Diagnostic::error(&e.file, e.line, format!("invalid config: {}", e.message))
+    }
+}
+
+impl From<&TemplateError> for Diagnostic {
+    fn from(e: &TemplateError) -> Self {
+        Diagnostic::error(&e.file, e.line, format!("invalid template: {}", e.message))
+    }
+}
+
+impl From<&InvalidLink> for Diagnostic {
+    fn from(invalid: &InvalidLink) -> Self {
+        let diagnostic = link_diagnostic(
+            &invalid.link,
+            format!("invalid link `{}`", invalid.link.link),
+        );
+        match &invalid.suggestion {
+            Some(suggestion) => diagnostic.with_help(format!("did you mean `{}`?", suggestion)),
+            None => diagnostic,
+        }
+    }
+}
+
+/// Points at where `link` was written, or at the generated page holding it otherwise.
+fn link_diagnostic(link: &Link, message: String) -> Diagnostic {
+    match &link.position {
+        Some(position) => Diagnostic::error(&position.file, position.line, message)
+            .with_column(position.column, position.length),
+        None => Diagnostic::error(&link.file, 0, message),
+    }
+}
 
 pub enum BuildError {
     InvalidLinks(InvalidLinks),
+    BrokenUrls(Vec<(UrlLink, UrlStatus)>),
+    FrontMatter(FrontMatterError),
+    Config(ConfigError),
+    Template(TemplateError),
+    InvalidFeed(Vec<Diagnostic>), // Feeds breaking the rules of their format
+    Read(PathBuf, std::io::Error), // A source file which couldn't be read
     IoError(std::io::Error),
+    Many(Vec<BuildError>), // Every error of a build, reported together
 }
 
-impl std::fmt::Debug for BuildError {
+impl BuildError {
+    /// Every problem the error is made of, as errors. An io error has no source position, so
+    /// it has none.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            BuildError::InvalidLinks(links) => links.0.iter().map(Diagnostic::from).collect(),
+            BuildError::BrokenUrls(urls) => urls
+                .iter()
+                .map(|(url, status)| {
+                    link_diagnostic(&url.0, format!("broken url `{}` ({})", url.0.link, status))
+                })
+                .collect(),
+            BuildError::FrontMatter(e) => vec![e.into()],
+            BuildError::Config(e) => vec![e.into()],
+            BuildError::Template(e) => vec![e.into()],
+            BuildError::InvalidFeed(problems) => problems.clone(),
+            BuildError::Read(file, e) => {
+                vec![Diagnostic::error(
+                    file,
+                    0,
+                    format!("couldn't read file: {}", e),
+                )]
+            }
+            BuildError::IoError(_) => Vec::new(),
+            BuildError::Many(errors) => errors.iter().flat_map(BuildError::diagnostics).collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for BuildError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            BuildError::InvalidLinks(links) => {
-                for link in links.0.iter() {
-                    write!(
+            BuildError::IoError(e) => write!(f, "{}: {}", Severity::Error, e),
+            BuildError::Many(errors) => {
+                // A broken template fails every page using it the same way
+                let mut reported: Vec<String> = Vec::new();
+                for e in errors.iter() {
+                    let reports = match e {
+                        BuildError::IoError(e) => vec![format!("{}: {}", Severity::Error, e)],
+                        e => e.diagnostics().iter().map(|d| d.to_string()).collect(),
+                    };
+                    for report in reports {
+                        if !reported.contains(&report) {
+                            reported.push(report);
+                        }
+                    }
+                }
+
+                for report in reported.iter() {
+                    write!(f, "{}\n\n", report)?;
+                }
+                match reported.len() {
+                    1 => write!(f, "{}: aborting due to 1 previous error", Severity::Error),
+                    n => write!(
                         f,
-                        "\nInvalid link: {} from file: {:?}",
-                        link.link, link.file
-                    )?;
+                        "{}: aborting due to {} previous errors",
+                        Severity::Error,
+                        n
+                    ),
+                }
+            }
+            _ => {
+                for (i, diagnostic) in self.diagnostics().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "\n\n")?;
+                    }
+                    write!(f, "{}", diagnostic)?;
                 }
                 Ok(())
             }
-            BuildError::IoError(e) => write!(f, "{:?}", e),
+        }
+    }
+}
+
+impl std::fmt::Debug for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for BuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BuildError::Read(_, e) | BuildError::IoError(e) => Some(e),
+            _ => None,
         }
     }
 }
@@ -0,0 +1,602 @@
+use crate::{ExternalLinksConfig, UrlLink};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Outcome of checking an external url.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UrlStatus {
+    Ok(u16),
+    Broken(u16),
+    /// The request itself failed (dns, tls, timeout...).
+    Unreachable(String),
+}
+
+impl UrlStatus {
+    pub fn from_code(code: u16) -> Self {
+        if code < 400 {
+            UrlStatus::Ok(code)
+        } else {
+            UrlStatus::Broken(code)
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        matches!(self, UrlStatus::Ok(_))
+    }
+
+    /// Failures which might go away on their own, and so aren't cached.
+    fn is_transient(&self) -> bool {
+        match self {
+            UrlStatus::Ok(_) => false,
+            UrlStatus::Broken(code) => *code == 429 || *code >= 500,
+            UrlStatus::Unreachable(_) => true,
+        }
+    }
+}
+
+impl std::fmt::Display for UrlStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UrlStatus::Ok(code) | UrlStatus::Broken(code) => write!(f, "status {code}"),
+            UrlStatus::Unreachable(e) => write!(f, "unreachable: {e}"),
+        }
+    }
+}
+
+/// Makes the actual requests, so the validator can run against something other than the
+/// network.
+pub trait HttpBackend: Send + Sync {
+    /// Status code returned for `url`, or why no response was received.
+    fn status(&self, url: &str) -> Result<u16, String>;
+}
+
+/// Backend answering from a fixed set of responses, for checking links without a network.
+/// Urls it doesn't know about are unreachable.
+#[derive(Debug, Clone, Default)]
+pub struct OfflineBackend {
+    responses: HashMap<String, u16>,
+}
+
+impl OfflineBackend {
+    pub fn new(responses: HashMap<String, u16>) -> Self {
+        Self { responses }
+    }
+
+    pub fn with(mut self, url: &str, code: u16) -> Self {
+        self.responses.insert(url.to_owned(), code);
+        self
+    }
+}
+
+impl HttpBackend for OfflineBackend {
+    fn status(&self, url: &str) -> Result<u16, String> {
+        self.responses
+            .get(url)
+            .copied()
+            .ok_or_else(|| format!("no offline response for {url}"))
+    }
+}
+
+/// Backend making real http requests.
+#[cfg(feature = "external-links")]
+pub struct UreqBackend {
+    agent: ureq::Agent,
+}
+
+#[cfg(feature = "external-links")]
+impl UreqBackend {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            agent: ureq::AgentBuilder::new()
+                .timeout(timeout)
+                .user_agent(concat!("panda/", env!("CARGO_PKG_VERSION")))
+                .build(),
+        }
+    }
+}
+
+#[cfg(feature = "external-links")]
+impl HttpBackend for UreqBackend {
+    fn status(&self, url: &str) -> Result<u16, String> {
+        let response = match self.agent.head(url).call() {
+            // Not every server supports HEAD requests
+            Err(ureq::Error::Status(405 | 501, _)) => self.agent.get(url).call(),
+            response => response,
+        };
+
+        match response {
+            Ok(response) => Ok(response.status()),
+            Err(ureq::Error::Status(code, _)) => Ok(code),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    status: UrlStatus,
+    checked_at: u64, // Seconds since the unix epoch
+}
+
+/// Results of previous checks, persisted between builds.
+#[derive(Debug)]
+pub struct UrlCache {
+    path: Option<PathBuf>,
+    ttl: Duration,
+    entries: BTreeMap<String, CacheEntry>,
+}
+
+impl UrlCache {
+    /// Loads the cache at `path`, starting from scratch if it's missing or unreadable.
+    pub fn load(path: Option<PathBuf>, ttl: Duration) -> Self {
+        let entries = path
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+
+        Self { path, ttl, entries }
+    }
+
+    /// Status of `url` if it was checked within the ttl.
+    pub fn get(&self, url: &str) -> Option<&UrlStatus> {
+        let entry = self.entries.get(url)?;
+        let age = now().saturating_sub(entry.checked_at);
+        (age < self.ttl.as_secs()).then_some(&entry.status)
+    }
+
+    pub fn insert(&mut self, url: &str, status: UrlStatus) {
+        if status.is_transient() {
+            self.entries.remove(url);
+            return;
+        }
+
+        self.entries.insert(
+            url.to_owned(),
+            CacheEntry {
+                status,
+                checked_at: now(),
+            },
+        );
+    }
+
+    /// Writes the cache back to disk, dropping expired entries.
+    pub fn save(&mut self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let now = now();
+        let ttl = self.ttl.as_secs();
+        self.entries
+            .retain(|_, entry| now.saturating_sub(entry.checked_at) < ttl);
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let contents =
+            serde_json::to_string_pretty(&self.entries).map_err(std::io::Error::other)?;
+        std::fs::write(path, contents)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Spaces out requests to the same host.
+struct RateLimiter {
+    interval: Duration,
+    next: Mutex<HashMap<String, Instant>>, // Earliest time of the next request per host
+}
+
+impl RateLimiter {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Blocks until a request to `host` is allowed.
+    fn wait(&self, host: &str) {
+        let now = Instant::now();
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = next.get(host).copied().unwrap_or(now).max(now);
+            next.insert(host.to_owned(), slot + self.interval);
+            slot
+        };
+
+        std::thread::sleep(slot.saturating_duration_since(now));
+    }
+}
+
+/// Host (and port) part of an http(s) url, `None` for anything else.
+fn host(url: &str) -> Option<&str> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let host = &rest[..end];
+    (!host.is_empty()).then_some(host)
+}
+
+/// Checks external links concurrently, with requests to the same host rate limited and results
+/// cached on disk between builds.
+pub struct ExternalLinkValidator {
+    backend: Box<dyn HttpBackend>,
+    cache: Mutex<UrlCache>,
+    limiter: RateLimiter,
+    concurrency: usize,
+}
+
+impl ExternalLinkValidator {
+    pub fn new(config: &ExternalLinksConfig, backend: Box<dyn HttpBackend>) -> Self {
+        Self {
+            backend,
+            cache: Mutex::new(UrlCache::load(
+                config.cache.clone(),
+                Duration::from_secs(config.ttl_hours * 60 * 60),
+            )),
+            limiter: RateLimiter::new(Duration::from_millis(config.per_host_interval_ms)),
+            concurrency: config.concurrency.max(1),
+        }
+    }
+
+    /// Validator making real http requests.
+    #[cfg(feature = "external-links")]
+    pub fn from_config(config: &ExternalLinksConfig) -> Self {
+        let backend = UreqBackend::new(Duration::from_secs(config.timeout_secs));
+        Self::new(config, Box::new(backend))
+    }
+
+    /// Checks every http(s) link, returning the ones which failed along with why. Each url is
+    /// only checked once, no matter how many pages link to it.
+    pub fn validate(&self, links: &[UrlLink]) -> Vec<(UrlLink, UrlStatus)> {
+        let mut urls: Vec<&str> = links
+            .iter()
+            .filter_map(|link| {
+                let url = strip_fragment(&link.0.link);
+                host(url).map(|_| url)
+            })
+            .collect();
+        urls.sort_unstable();
+        urls.dedup();
+
+        let statuses = self.check_all(urls);
+
+        links
+            .iter()
+            .filter_map(|link| {
+                let status = statuses.get(strip_fragment(&link.0.link))?;
+                (!status.is_ok()).then(|| (link.clone(), status.clone()))
+            })
+            .collect()
+    }
+
+    fn check_all<'a>(&self, urls: Vec<&'a str>) -> HashMap<&'a str, UrlStatus> {
+        let queue = Mutex::new(urls.into_iter());
+        let statuses = Mutex::new(HashMap::new());
+
+        std::thread::scope(|scope| {
+            for _ in 0..self.concurrency {
+                scope.spawn(|| {
+                    loop {
+                        let Some(url) = queue.lock().unwrap().next() else {
+                            break;
+                        };
+                        let status = self.check(url);
+                        statuses.lock().unwrap().insert(url, status);
+                    }
+                });
+            }
+        });
+
+        statuses.into_inner().unwrap()
+    }
+
+    fn check(&self, url: &str) -> UrlStatus {
+        if let Some(status) = self.cache.lock().unwrap().get(url) {
+            return status.clone();
+        }
+
+        if let Some(host) = host(url) {
+            self.limiter.wait(host);
+        }
+
+        let status = match self.backend.status(url) {
+            Ok(code) => UrlStatus::from_code(code),
+            Err(e) => UrlStatus::Unreachable(e),
+        };
+
+        self.cache.lock().unwrap().insert(url, status.clone());
+        status
+    }
+
+    pub fn save_cache(&self) -> std::io::Result<()> {
+        self.cache.lock().unwrap().save()
+    }
+}
+
+fn strip_fragment(url: &str) -> &str {
+    url.split_once('#').map_or(url, |(url, _)| url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Link;
+    use std::sync::Arc;
+
+    /// Every url requested, along with when.
+    type Requests = Arc<Mutex<Vec<(String, Instant)>>>;
+
+    /// Answers like an [`OfflineBackend`], recording every request.
+    struct Recording {
+        backend: OfflineBackend,
+        requests: Requests,
+    }
+
+    impl HttpBackend for Recording {
+        fn status(&self, url: &str) -> Result<u16, String> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((url.to_owned(), Instant::now()));
+            self.backend.status(url)
+        }
+    }
+
+    fn validator(backend: OfflineBackend, interval_ms: u64) -> (ExternalLinkValidator, Requests) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let config = ExternalLinksConfig {
+            concurrency: 4,
+            per_host_interval_ms: interval_ms,
+            ..Default::default()
+        };
+        let backend = Recording {
+            backend,
+            requests: requests.clone(),
+        };
+        (
+            ExternalLinkValidator::new(&config, Box::new(backend)),
+            requests,
+        )
+    }
+
+    fn links(urls: &[&str]) -> Vec<UrlLink> {
+        urls.iter()
+            .map(|url| {
+                UrlLink(Link {
+                    link: url.to_string(),
+                    file: PathBuf::from("page.html"),
+                    position: None,
+                })
+            })
+            .collect()
+    }
+
+    fn requested(requests: &Requests, url: &str) -> usize {
+        requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(requested, _)| requested == url)
+            .count()
+    }
+
+    #[test]
+    fn cached_results_expire_after_the_ttl() {
+        let ttl = Duration::from_secs(60 * 60);
+        let mut cache = UrlCache::load(None, ttl);
+        cache.insert("https://fresh.example", UrlStatus::Ok(200));
+        cache.entries.insert(
+            "https://stale.example".to_owned(),
+            CacheEntry {
+                status: UrlStatus::Ok(200),
+                checked_at: now() - ttl.as_secs(),
+            },
+        );
+
+        assert_eq!(
+            cache.get("https://fresh.example"),
+            Some(&UrlStatus::Ok(200))
+        );
+        assert_eq!(cache.get("https://stale.example"), None);
+    }
+
+    #[test]
+    fn transient_failures_are_not_cached() {
+        let backend = OfflineBackend::default()
+            .with("https://gone.example/", 404)
+            .with("https://down.example/", 503)
+            .with("https://busy.example/", 429);
+        let (validator, requests) = validator(backend, 0);
+        let urls = [
+            "https://gone.example/",
+            "https://down.example/",
+            "https://busy.example/",
+            "https://unreachable.example/",
+        ];
+
+        for _ in 0..2 {
+            assert_eq!(validator.validate(&links(&urls)).len(), urls.len());
+        }
+
+        assert_eq!(requested(&requests, "https://gone.example/"), 1);
+        assert_eq!(requested(&requests, "https://down.example/"), 2);
+        assert_eq!(requested(&requests, "https://busy.example/"), 2);
+        assert_eq!(requested(&requests, "https://unreachable.example/"), 2);
+    }
+
+    #[test]
+    fn repeated_urls_are_checked_once() {
+        let backend = OfflineBackend::default().with("https://example.com/page", 404);
+        let (validator, requests) = validator(backend, 0);
+
+        let broken = validator.validate(&links(&[
+            "https://example.com/page",
+            "https://example.com/page#intro",
+            "https://example.com/page",
+        ]));
+
+        // Every link to it is reported, each from its own page
+        assert_eq!(broken.len(), 3);
+        assert_eq!(requested(&requests, "https://example.com/page"), 1);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn requests_to_the_same_host_are_spaced_out() {
+        let interval = Duration::from_millis(50);
+        let urls = [
+            "https://slow.example/1",
+            "https://slow.example/2",
+            "https://slow.example/3",
+            "https://other.example/",
+        ];
+        let backend = urls.iter().fold(OfflineBackend::default(), |backend, url| {
+            backend.with(url, 200)
+        });
+        let (validator, requests) = validator(backend, interval.as_millis() as u64);
+
+        let start = Instant::now();
+        assert!(validator.validate(&links(&urls)).is_empty());
+
+        let mut times: Vec<Instant> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(url, _)| url.starts_with("https://slow.example/"))
+            .map(|(_, time)| *time)
+            .collect();
+        times.sort();
+        assert_eq!(times.len(), 3);
+        // Each request waits for the slot after the previous one's
+        for (i, time) in times.iter().enumerate() {
+            assert!(*time - start >= interval * i as u32);
+        }
+    }
+
+    #[test]
+    fn cache_survives_a_save_and_load() {
+        let ttl = Duration::from_secs(60 * 60);
+        let path =
+            std::env::temp_dir().join(format!("panda-url-cache-{}.json", std::process::id()));
+        let mut cache = UrlCache::load(Some(path.clone()), ttl);
+        cache.insert("https://fresh.example", UrlStatus::Broken(404));
+        cache.entries.insert(
+            "https://stale.example".to_owned(),
+            CacheEntry {
+                status: UrlStatus::Ok(200),
+                checked_at: now() - ttl.as_secs(),
+            },
+        );
+        cache.save().unwrap();
+
+        let loaded = UrlCache::load(Some(path.clone()), ttl);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            loaded.get("https://fresh.example"),
+            Some(&UrlStatus::Broken(404))
+        );
+        // Expired entries aren't written back at all
+        assert!(!loaded.entries.contains_key("https://stale.example"));
+    }
+
+    /// Serves `respond(method, path)` as the status of every request on a local port, and
+    /// returns the server's url along with the requests it received.
+    #[cfg(feature = "external-links")]
+    fn serve(respond: fn(&str, &str) -> u16) -> (String, Arc<Mutex<Vec<String>>>) {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(&stream);
+                let mut request = String::new();
+                if reader.read_line(&mut request).is_err() {
+                    continue;
+                }
+                // Skip the headers, requests have no body
+                let mut header = String::new();
+                while reader.read_line(&mut header).is_ok_and(|n| n > 2) {
+                    header.clear();
+                }
+
+                let mut parts = request.split_whitespace();
+                let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+                received.lock().unwrap().push(format!("{method} {path}"));
+                let response = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    respond(method, path)
+                );
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        (url, requests)
+    }
+
+    #[cfg(feature = "external-links")]
+    #[test]
+    fn ureq_backend_against_a_local_server() {
+        let (url, requests) = serve(|method, path| match (method, path) {
+            (_, "/ok") => 200,
+            (_, "/missing") => 404,
+            ("HEAD", "/no-head") => 405,
+            ("HEAD", "/head-unsupported") => 501,
+            ("GET", "/no-head" | "/head-unsupported") => 200,
+            _ => 500,
+        });
+        let backend = UreqBackend::new(Duration::from_secs(5));
+
+        assert_eq!(backend.status(&format!("{url}/ok")), Ok(200));
+        assert_eq!(backend.status(&format!("{url}/missing")), Ok(404));
+        assert_eq!(backend.status(&format!("{url}/broken")), Ok(500));
+        assert_eq!(backend.status(&format!("{url}/no-head")), Ok(200));
+        assert_eq!(backend.status(&format!("{url}/head-unsupported")), Ok(200));
+
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "HEAD /ok",
+                "HEAD /missing",
+                "HEAD /broken",
+                "HEAD /no-head",
+                "GET /no-head",
+                "HEAD /head-unsupported",
+                "GET /head-unsupported",
+            ]
+        );
+    }
+
+    #[cfg(feature = "external-links")]
+    #[test]
+    fn ureq_backend_reports_unreachable_servers() {
+        // Nothing listens on the port once the listener is dropped
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let backend = UreqBackend::new(Duration::from_secs(5));
+
+        let status = match backend.status(&format!("http://{address}/")) {
+            Ok(code) => UrlStatus::from_code(code),
+            Err(e) => UrlStatus::Unreachable(e),
+        };
+        assert!(matches!(status, UrlStatus::Unreachable(_)), "{status}");
+        assert!(status.is_transient());
+    }
+}
@@ -0,0 +1,247 @@
+use crate::Config;
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Format the collected posts are published in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedFormat {
+    /// RSS 2.0, as `rss.xml`.
+    Rss,
+    /// Atom 1.0, as `atom.xml`.
+    Atom,
+    /// JSON Feed 1.1, as `feed.json`.
+    Json,
+}
+
+impl FeedFormat {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "rss.xml",
+            FeedFormat::Atom => "atom.xml",
+            FeedFormat::Json => "feed.json",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml",
+            FeedFormat::Atom => "application/atom+xml",
+            FeedFormat::Json => "application/feed+json",
+        }
+    }
+
+    /// Name readers know the format by.
+    pub fn name(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "RSS",
+            FeedFormat::Atom => "Atom",
+            FeedFormat::Json => "JSON Feed",
+        }
+    }
+
+    /// Writes `channel` in this format.
+    pub fn write(self, channel: &rss::Channel, config: &Config) -> std::io::Result<Vec<u8>> {
+        match self {
+            FeedFormat::Rss => channel
+                .pretty_write_to(Vec::new(), b' ', 2)
+                .map_err(std::io::Error::other),
+            FeedFormat::Atom => {
+                let write_config = atom_syndication::WriteConfig {
+                    write_document_declaration: true,
+                    indent_size: Some(2),
+                };
+                to_atom(channel, config)
+                    .write_with_config(Vec::new(), write_config)
+                    .map_err(std::io::Error::other)
+            }
+            FeedFormat::Json => serde_json::to_vec_pretty(&to_json_feed(channel, config))
+                .map_err(std::io::Error::other),
+        }
+    }
+}
+
+/// Every format, which is what sites publish unless their config picks some.
+pub fn all_feed_formats() -> Vec<FeedFormat> {
+    vec![FeedFormat::Rss, FeedFormat::Atom, FeedFormat::Json]
+}
+
+/// Date of an rss item, which rss keeps as an rfc 2822 string.
+fn item_date(item: &rss::Item) -> Option<DateTime<FixedOffset>> {
+    item.pub_date()
+        .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+}
+
+/// Id of an rss item, its guid or else its link.
+fn item_id(item: &rss::Item) -> &str {
+    item.guid()
+        .map(|guid| guid.value())
+        .or(item.link())
+        .unwrap_or_default()
+}
+
+/// Authors of an rss item, which are kept in `dc:creator`.
+fn item_authors(item: &rss::Item) -> &[String] {
+    item.dublin_core_ext()
+        .map(|dc| dc.creators())
+        .unwrap_or_default()
+}
+
+/// The same feed as `channel`, in Atom. Atom entries must say when they were updated, so
+/// undated items are left out rather than dated to the unix epoch. They must have an author
+/// too, so without one in the config the site itself is the feed's author.
+pub fn to_atom(channel: &rss::Channel, config: &Config) -> atom_syndication::Feed {
+    use atom_syndication::{Category, Content, Entry, Feed, Link, Person, Text};
+
+    let person = |name: &String| Person {
+        name: name.clone(),
+        ..Default::default()
+    };
+
+    let link = |href: &str, rel: &str, mime_type: Option<&str>| Link {
+        href: href.to_owned(),
+        rel: rel.to_owned(),
+        mime_type: mime_type.map(str::to_owned),
+        ..Default::default()
+    };
+
+    let entries: Vec<Entry> = channel
+        .items()
+        .iter()
+        .filter_map(|item| {
+            let url = item.link().unwrap_or_default();
+            let date = item_date(item)?;
+
+            let mut entry = Entry::default();
+            entry.set_title(item.title().unwrap_or_default());
+            entry.set_id(item_id(item));
+            entry.set_updated(date);
+            entry.set_published(Some(date));
+            entry.set_links(vec![link(url, "alternate", Some("text/html"))]);
+            entry.set_authors(item_authors(item).iter().map(person).collect::<Vec<_>>());
+            entry.set_categories(
+                item.categories()
+                    .iter()
+                    .map(|category| Category {
+                        term: category.name().to_owned(),
+                        ..Default::default()
+                    })
+                    .collect::<Vec<_>>(),
+            );
+            entry.set_summary(item.description().map(Text::plain));
+            entry.set_content(item.content().map(|content| Content {
+                value: Some(content.to_owned()),
+                content_type: Some("html".to_owned()),
+                ..Default::default()
+            }));
+            Some(entry)
+        })
+        .collect();
+
+    // Atom needs a date even when no post has one, then the feed is as new as the build
+    let updated = entries
+        .iter()
+        .map(|entry| entry.updated)
+        .max()
+        .unwrap_or_else(|| Utc::now().fixed_offset());
+
+    let mut feed = Feed::default();
+    feed.set_title(channel.title());
+    feed.set_id(config.url(""));
+    feed.set_updated(updated);
+    feed.set_subtitle(Some(Text::plain(channel.description())).filter(|s| !s.value.is_empty()));
+    feed.set_lang(Some(config.language.clone()));
+    feed.set_links(vec![
+        link(&config.url(""), "alternate", Some("text/html")),
+        link(
+            &config.url(FeedFormat::Atom.file_name()),
+            "self",
+            Some(FeedFormat::Atom.mime_type()),
+        ),
+    ]);
+    feed.set_authors(vec![person(
+        config.author.as_ref().unwrap_or(&config.title),
+    )]);
+    feed.set_entries(entries);
+    feed
+}
+
+/// A feed in the JSON Feed 1.1 format, see <https://www.jsonfeed.org/version/1.1/>.
+#[derive(Debug, Serialize)]
+pub struct JsonFeed {
+    pub version: &'static str,
+    pub title: String,
+    pub home_page_url: String,
+    pub feed_url: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    pub language: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<JsonFeedAuthor>,
+    pub items: Vec<JsonFeedItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonFeedAuthor {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonFeedItem {
+    pub id: String,
+    pub url: String,
+    pub title: String,
+    /// Items need some content, even if empty.
+    pub content_html: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_published: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<JsonFeedAuthor>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// The same feed as `channel`, in JSON Feed.
+pub fn to_json_feed(channel: &rss::Channel, config: &Config) -> JsonFeed {
+    let items = channel
+        .items()
+        .iter()
+        .map(|item| {
+            let url = item.link().unwrap_or_default().to_owned();
+            JsonFeedItem {
+                id: item_id(item).to_owned(),
+                url,
+                title: item.title().unwrap_or_default().to_owned(),
+                content_html: item.content().unwrap_or_default().to_owned(),
+                summary: item.description().map(str::to_owned),
+                date_published: item_date(item).map(|date| date.to_rfc3339()),
+                authors: item_authors(item)
+                    .iter()
+                    .map(|name| JsonFeedAuthor { name: name.clone() })
+                    .collect(),
+                tags: item
+                    .categories()
+                    .iter()
+                    .map(|category| category.name().to_owned())
+                    .collect(),
+            }
+        })
+        .collect();
+
+    JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: channel.title().to_owned(),
+        home_page_url: config.url(""),
+        feed_url: config.url(FeedFormat::Json.file_name()),
+        description: channel.description().to_owned(),
+        language: config.language.clone(),
+        authors: config
+            .author
+            .iter()
+            .map(|name| JsonFeedAuthor { name: name.clone() })
+            .collect(),
+        items,
+    }
+}
@@ -0,0 +1,588 @@
+use crate::{Diagnostic, FeedFormat, extract_links};
+use chrono::DateTime;
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+use std::collections::HashMap;
+use std::path::Path;
+
+const ATOM_NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+
+/// Characters of the offending line quoted by problems.
+const SNIPPET_LENGTH: usize = 100;
+
+/// Checks the feed `contents`, published as `file`, against the rules of its format: that it's
+/// well-formed xml, has every required element, writes dates the way the format wants them,
+/// only links to absolute urls and gives every item its own id.
+///
+/// Nothing is fetched, so it works offline unlike the online validators. JSON feeds are written
+/// straight from their types and aren't checked.
+pub fn validate_feed(format: FeedFormat, file: &Path, contents: &str) -> Vec<Diagnostic> {
+    let rules = match format {
+        FeedFormat::Rss => Validator::rss,
+        FeedFormat::Atom => Validator::atom,
+        FeedFormat::Json => return Vec::new(),
+    };
+
+    let mut validator = Validator {
+        file,
+        contents,
+        problems: Vec::new(),
+    };
+
+    let root = match parse(contents) {
+        Ok(root) => root,
+        Err((line, message)) => {
+            validator.error(line, format!("malformed xml: {message}"));
+            return validator.problems;
+        }
+    };
+
+    rules(&mut validator, &root);
+    validator.problems
+}
+
+/// Element of a parsed feed, only keeping what the rules look at.
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    text: String,
+    children: Vec<Element>,
+    line: usize,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn text(&self) -> &str {
+        self.text.trim()
+    }
+}
+
+/// Parses `contents` into its root element, or returns the line and reason it isn't well-formed.
+fn parse(contents: &str) -> Result<Element, (usize, String)> {
+    let line_of = |offset: u64| {
+        contents[..(offset as usize).min(contents.len())]
+            .matches('\n')
+            .count()
+            + 1
+    };
+
+    let mut reader = Reader::from_str(contents);
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root = None;
+
+    loop {
+        let offset = reader.buffer_position();
+        let event = reader
+            .read_event()
+            .map_err(|e| (line_of(reader.error_position()), e.to_string()))?;
+        let line = line_of(offset);
+
+        match event {
+            Event::Start(start) | Event::Empty(start) if root.is_some() => {
+                let name = String::from_utf8_lossy(start.name().as_ref()).into_owned();
+                return Err((line, format!("`{name}` is a second root element")));
+            }
+            Event::Start(start) => stack.push(element(&start, line)?),
+            Event::Empty(start) => {
+                let element = element(&start, line)?;
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => root = Some(element),
+                }
+            }
+            Event::End(_) => {
+                // The reader already checks that end tags match their start
+                let element = stack.pop().unwrap_or_default();
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => root = Some(element),
+                }
+            }
+            Event::Text(text) => {
+                let text = text.decode().map_err(|e| (line, e.to_string()))?;
+                match stack.last_mut() {
+                    Some(element) => element.text.push_str(&text),
+                    None if text.trim().is_empty() => (),
+                    None => return Err((line, "text outside of the root element".to_owned())),
+                }
+            }
+            Event::CData(data) => {
+                let data = data.decode().map_err(|e| (line, e.to_string()))?;
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&data);
+                }
+            }
+            Event::GeneralRef(reference) => {
+                let entity = reference.decode().map_err(|e| (line, e.to_string()))?;
+                let resolved = match quick_xml::escape::resolve_predefined_entity(&entity) {
+                    Some(resolved) => resolved.chars().next(),
+                    None => reference
+                        .resolve_char_ref()
+                        .map_err(|e| (line, e.to_string()))?,
+                };
+                let Some(resolved) = resolved else {
+                    return Err((line, format!("undefined entity `&{entity};`")));
+                };
+                if let Some(element) = stack.last_mut() {
+                    element.text.push(resolved);
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+
+    if let Some(unclosed) = stack.last() {
+        return Err((
+            unclosed.line,
+            format!("`{}` is never closed", unclosed.name),
+        ));
+    }
+    root.ok_or((1, "no root element".to_owned()))
+}
+
+fn element(start: &BytesStart, line: usize) -> Result<Element, (usize, String)> {
+    let mut attributes = Vec::new();
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(|e| (line, e.to_string()))?;
+        let value = String::from_utf8_lossy(&attribute.value);
+        let value = quick_xml::escape::unescape(&value).map_err(|e| (line, e.to_string()))?;
+        attributes.push((
+            String::from_utf8_lossy(attribute.key.as_ref()).into_owned(),
+            value.into_owned(),
+        ));
+    }
+
+    Ok(Element {
+        name: String::from_utf8_lossy(start.name().as_ref()).into_owned(),
+        attributes,
+        line,
+        ..Default::default()
+    })
+}
+
+/// Whether `url` has a scheme, so it means the same wherever the feed is read.
+fn is_absolute(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+struct Validator<'a> {
+    file: &'a Path,
+    contents: &'a str,
+    problems: Vec<Diagnostic>,
+}
+
+impl Validator<'_> {
+    fn error(&mut self, line: usize, message: impl Into<String>) {
+        // Whole items can be on a single line
+        let snippet = self
+            .contents
+            .lines()
+            .nth(line.saturating_sub(1))
+            .map(|line| match line.char_indices().nth(SNIPPET_LENGTH) {
+                Some((end, _)) => format!("{}...", &line[..end]),
+                None => line.to_owned(),
+            });
+        let mut diagnostic = Diagnostic::error(self.file, line, message);
+        diagnostic.snippet = snippet;
+        self.problems.push(diagnostic);
+    }
+
+    /// Checks that `parent` has a `name` child, which isn't empty unless `allow_empty`.
+    fn require<'e>(
+        &mut self,
+        parent: &'e Element,
+        name: &str,
+        allow_empty: bool,
+    ) -> Option<&'e Element> {
+        match parent.child(name) {
+            None => {
+                self.error(
+                    parent.line,
+                    format!("`{}` is missing its `{name}`", parent.name),
+                );
+                None
+            }
+            Some(child) if !allow_empty && child.text().is_empty() => {
+                self.error(child.line, format!("`{name}` is empty"));
+                Some(child)
+            }
+            Some(child) => Some(child),
+        }
+    }
+
+    fn url(&mut self, line: usize, what: &str, url: &str) {
+        if !is_absolute(url) {
+            self.error(line, format!("{what} `{url}` isn't an absolute url"));
+        } else if url.contains(char::is_whitespace) {
+            self.error(line, format!("{what} `{url}` has unencoded spaces"));
+        }
+    }
+
+    /// Checks the urls inside the html of `element`, which readers can't resolve.
+    fn html(&mut self, element: &Element) {
+        for url in extract_links(element.text()) {
+            if !url.starts_with('#') {
+                self.url(element.line, "link", &url);
+            }
+        }
+    }
+
+    fn rfc2822(&mut self, element: Option<&Element>) {
+        if let Some(element) = element
+            && DateTime::parse_from_rfc2822(element.text()).is_err()
+        {
+            self.error(
+                element.line,
+                format!(
+                    "`{}` isn't an rfc 822 date: {}",
+                    element.name,
+                    element.text()
+                ),
+            );
+        }
+    }
+
+    fn rfc3339(&mut self, element: Option<&Element>) {
+        if let Some(element) = element
+            && DateTime::parse_from_rfc3339(element.text()).is_err()
+        {
+            self.error(
+                element.line,
+                format!(
+                    "`{}` isn't an rfc 3339 date: {}",
+                    element.name,
+                    element.text()
+                ),
+            );
+        }
+    }
+
+    /// Checks that every id is only used once.
+    fn unique<'e>(&mut self, ids: impl Iterator<Item = &'e Element>, what: &str) {
+        let mut seen: HashMap<&str, usize> = HashMap::new();
+        for id in ids {
+            if let Some(first) = seen.insert(id.text(), id.line) {
+                self.error(
+                    id.line,
+                    format!("{what} `{}` was already used on line {first}", id.text()),
+                );
+            }
+        }
+    }
+
+    /// Rules of <https://www.rssboard.org/rss-specification>.
+    fn rss(&mut self, root: &Element) {
+        if root.name != "rss" {
+            self.error(
+                root.line,
+                format!("expected an `rss` root, found `{}`", root.name),
+            );
+            return;
+        }
+        if root.attribute("version") != Some("2.0") {
+            self.error(root.line, "`rss` doesn't have version 2.0");
+        }
+
+        let Some(channel) = self.require(root, "channel", true) else {
+            return;
+        };
+
+        self.require(channel, "title", false);
+        self.require(channel, "description", true);
+        if let Some(link) = self.require(channel, "link", false) {
+            self.url(link.line, "channel link", link.text());
+        }
+        self.rfc2822(channel.child("pubDate"));
+        self.rfc2822(channel.child("lastBuildDate"));
+
+        for item in channel.children("item") {
+            if item.child("title").is_none() && item.child("description").is_none() {
+                self.error(
+                    item.line,
+                    "`item` needs either a `title` or a `description`",
+                );
+            }
+            if let Some(link) = item.child("link") {
+                self.url(link.line, "item link", link.text());
+            }
+            if let Some(guid) = item.child("guid")
+                && guid.attribute("isPermaLink") != Some("false")
+            {
+                self.url(guid.line, "permalink guid", guid.text());
+            }
+            self.rfc2822(item.child("pubDate"));
+            if let Some(content) = item.child("content:encoded") {
+                self.html(content);
+            }
+        }
+
+        let guids = channel
+            .children("item")
+            .filter_map(|item| item.child("guid"));
+        self.unique(guids, "guid");
+    }
+
+    /// Rules of <https://www.rfc-editor.org/rfc/rfc4287>.
+    fn atom(&mut self, root: &Element) {
+        if root.name != "feed" {
+            self.error(
+                root.line,
+                format!("expected a `feed` root, found `{}`", root.name),
+            );
+            return;
+        }
+        if root.attribute("xmlns") != Some(ATOM_NAMESPACE) {
+            self.error(
+                root.line,
+                format!("`feed` isn't in the {ATOM_NAMESPACE} namespace"),
+            );
+        }
+
+        self.atom_common(root);
+        let has_author = root.child("author").is_some();
+
+        for entry in root.children("entry") {
+            self.atom_common(entry);
+            if !has_author && entry.child("author").is_none() {
+                self.error(
+                    entry.line,
+                    "`entry` has no `author`, and neither does the feed",
+                );
+            }
+            self.rfc3339(entry.child("published"));
+
+            let alternate = entry
+                .children("link")
+                .any(|link| link.attribute("rel").unwrap_or("alternate") == "alternate");
+            if entry.child("content").is_none() && !alternate {
+                self.error(
+                    entry.line,
+                    "`entry` needs either a `content` or an alternate `link`",
+                );
+            }
+            for html in ["content", "summary"]
+                .into_iter()
+                .filter_map(|name| entry.child(name))
+            {
+                if html.attribute("type") == Some("html") {
+                    self.html(html);
+                }
+            }
+        }
+
+        let ids = root.children("entry").filter_map(|entry| entry.child("id"));
+        self.unique(ids, "id");
+    }
+
+    /// Rules shared by feeds and their entries.
+    fn atom_common(&mut self, element: &Element) {
+        if let Some(id) = self.require(element, "id", false) {
+            self.url(id.line, "id", id.text());
+        }
+        self.require(element, "title", true);
+        let updated = self.require(element, "updated", false);
+        self.rfc3339(updated);
+
+        for link in element.children("link") {
+            match link.attribute("href") {
+                Some(href) => self.url(link.line, "link", href),
+                None => self.error(link.line, "`link` has no `href`"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RSS: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+  <channel>
+    <title>Blog</title>
+    <link>https://example.com</link>
+    <description>Posts</description>
+    <lastBuildDate>Sun, 21 Sep 2025 14:47:56 -0300</lastBuildDate>
+    <item>
+      <title>First</title>
+      <link>https://example.com/posts/first.html</link>
+      <guid isPermaLink="false">tag:example.com,2025-09-21:posts/first</guid>
+      <pubDate>Sun, 21 Sep 2025 14:47:56 -0300</pubDate>
+      <content:encoded><![CDATA[<a href="https://example.com/posts/second.html">second</a>]]></content:encoded>
+    </item>
+    <item>
+      <title>Second</title>
+      <link>https://example.com/posts/second.html</link>
+      <guid isPermaLink="false">tag:example.com,2025-09-20:posts/second</guid>
+      <pubDate>Sat, 20 Sep 2025 10:00:00 -0300</pubDate>
+    </item>
+  </channel>
+</rss>
+"#;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Blog</title>
+  <id>https://example.com/</id>
+  <updated>2025-09-21T14:47:56-03:00</updated>
+  <link href="https://example.com/atom.xml" rel="self"/>
+  <entry>
+    <title>First</title>
+    <id>tag:example.com,2025-09-21:posts/first</id>
+    <updated>2025-09-21T14:47:56-03:00</updated>
+    <author><name>Eduardo</name></author>
+    <link href="https://example.com/posts/first.html" rel="alternate"/>
+  </entry>
+  <entry>
+    <title>Second</title>
+    <id>tag:example.com,2025-09-20:posts/second</id>
+    <updated>2025-09-20T10:00:00-03:00</updated>
+    <author><name>Eduardo</name></author>
+    <link href="https://example.com/posts/second.html" rel="alternate"/>
+  </entry>
+</feed>
+"#;
+
+    /// Messages of the problems found in `contents`, with the line they're on.
+    fn problems(format: FeedFormat, contents: &str) -> Vec<(usize, String)> {
+        validate_feed(format, Path::new("feed.xml"), contents)
+            .into_iter()
+            .map(|problem| (problem.line, problem.message))
+            .collect()
+    }
+
+    /// Single problem found in `contents`, which must be the only one.
+    fn problem(format: FeedFormat, contents: &str) -> (usize, String) {
+        let mut problems = problems(format, contents);
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        problems.remove(0)
+    }
+
+    #[test]
+    fn valid_feeds_have_no_problems() {
+        assert_eq!(problems(FeedFormat::Rss, RSS), []);
+        assert_eq!(problems(FeedFormat::Atom, ATOM), []);
+    }
+
+    #[test]
+    fn malformed_xml() {
+        let contents = RSS.replace("</title>\n    <link>", "</titel>\n    <link>");
+        let (line, message) = problem(FeedFormat::Rss, &contents);
+        assert_eq!(line, 4);
+        assert!(message.starts_with("malformed xml:"), "{message}");
+
+        let (_, message) = problem(FeedFormat::Atom, "<feed>");
+        assert_eq!(message, "malformed xml: `feed` is never closed");
+    }
+
+    #[test]
+    fn missing_channel_title() {
+        let contents = RSS.replace("    <title>Blog</title>\n", "");
+        assert_eq!(
+            problem(FeedFormat::Rss, &contents),
+            (3, "`channel` is missing its `title`".to_owned())
+        );
+    }
+
+    #[test]
+    fn missing_channel_link() {
+        let contents = RSS.replace("    <link>https://example.com</link>\n", "");
+        assert_eq!(
+            problem(FeedFormat::Rss, &contents),
+            (3, "`channel` is missing its `link`".to_owned())
+        );
+    }
+
+    #[test]
+    fn bad_pub_date() {
+        let contents = RSS.replace("Sat, 20 Sep 2025 10:00:00 -0300", "2025-09-20");
+        assert_eq!(
+            problem(FeedFormat::Rss, &contents),
+            (19, "`pubDate` isn't an rfc 822 date: 2025-09-20".to_owned())
+        );
+    }
+
+    #[test]
+    fn relative_link_in_content() {
+        let contents = RSS.replace("https://example.com/posts/second.html\">", "second.html\">");
+        assert_eq!(
+            problem(FeedFormat::Rss, &contents),
+            (13, "link `second.html` isn't an absolute url".to_owned())
+        );
+    }
+
+    #[test]
+    fn fragments_in_content_are_fine() {
+        let contents = RSS.replace("https://example.com/posts/second.html\">", "#second\">");
+        assert_eq!(problems(FeedFormat::Rss, &contents), []);
+    }
+
+    #[test]
+    fn duplicate_guid() {
+        let contents = RSS.replace("2025-09-20:posts/second", "2025-09-21:posts/first");
+        assert_eq!(
+            problem(FeedFormat::Rss, &contents),
+            (
+                18,
+                "guid `tag:example.com,2025-09-21:posts/first` was already used on line 11"
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn duplicate_id() {
+        let contents = ATOM.replace("2025-09-20:posts/second", "2025-09-21:posts/first");
+        assert_eq!(
+            problem(FeedFormat::Atom, &contents),
+            (
+                16,
+                "id `tag:example.com,2025-09-21:posts/first` was already used on line 9".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn entry_without_author() {
+        let contents = ATOM.replacen("    <author><name>Eduardo</name></author>\n", "", 1);
+        assert_eq!(
+            problem(FeedFormat::Atom, &contents),
+            (
+                7,
+                "`entry` has no `author`, and neither does the feed".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn feed_author_covers_its_entries() {
+        let contents = ATOM
+            .replace("    <author><name>Eduardo</name></author>\n", "")
+            .replace(
+                "<title>Blog</title>",
+                "<title>Blog</title>\n  <author><name>Eduardo</name></author>",
+            );
+        assert_eq!(problems(FeedFormat::Atom, &contents), []);
+    }
+}
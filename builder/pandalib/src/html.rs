@@ -1,17 +1,13 @@
 use crate::page::*;
+use crate::tags::*;
+use crate::{Config, FeedFormat};
+use std::collections::BTreeMap;
+use std::path::Path;
 
-pub fn table_of_contents(source: String) -> String {
-    let header_ids: Vec<&str> = source
-        .split("<h2 id=\"")
-        .skip(1)
-        .map(|id| {
-            &id[..id
-                .find('\"')
-                .unwrap_or_else(|| panic!("Expected a final '\"' after parsing h2 id in:\n{id}"))]
-        })
-        .collect();
-
-    format_toc(header_ids) + &source
+/// Prepends a table of contents linking to the `h2` headings of `source`, whose ids are
+/// `header_ids`.
+pub fn table_of_contents(source: String, header_ids: &[String]) -> String {
+    format_toc(header_ids.iter().map(String::as_str).collect()) + &source
 }
 
 pub fn format_toc(titles: Vec<&str>) -> String {
@@ -38,79 +34,84 @@ pub fn format_toc(titles: Vec<&str>) -> String {
     toc
 }
 
-pub fn format_header(title: &str, root: &str) -> String {
-    format!(
-        "<!doctype html>\
-    <html lang=\"en-US\" data-webtui-theme=\"catppuccin-mocha\">\
-    <head> \
-    <title>{}</title> \
-    <link href=\"{}webtui.css\" rel=\"stylesheet\" type=\"text/css\" media=\"all\"> \
-    <link href='https://fonts.googleapis.com/css?family=Fira Mono' rel='stylesheet'> \
-    <link rel=\"alternate\" type=\"application/rss+xml\" title=\"RSS\"\
-    href=\"https://eduardorittner.github.io/rss.xml\">\
-    <meta charset=\"UTF-8\"> \
-    <script type=\"text/x-mathjax-config\"> \
-    MathJax.Hub.Config({{ \
-    tex2jax: {{inlineMath: [['$','$'], ['\\\\(','\\\\)']]}} \
-    }}); \
-    </script> \
-    <script type=\"text/javascript\" \
-    src=\"https://cdn.mathjax.org/mathjax/latest/MathJax.js?config=TeX-AMS-MML_HTMLorMML\"> \
-    </script> \
-    </head> \
-    ",
-        title, root
-    )
-}
-
-pub fn format_navbar(prefix: &str, kind: Category) -> String {
-    let mut home = "";
-    let mut post = "";
-    let mut note = "";
-    let mut link = "";
+/// Lists `pages` along with their dates, with the pages' paths taken relative to `prefix`.
+pub fn format_page_list(pages: &[PageSummary], prefix: &str, config: &Config) -> String {
+    let mut list = String::with_capacity(1024);
 
-    match kind {
-        Category::Home => home = "active",
-        Category::Post => post = "active",
-        Category::Note => note = "active",
-        Category::Link => link = "active",
-        _ => (),
+    list.push_str("<ul class=\"page-list\">");
+    for page in pages.iter() {
+        list.push_str("<li>");
+        if let Some(date) = &page.date {
+            list.push_str(&format!(
+                "<span class=\"date\">{}</span> ",
+                date.format("%Y-%m-%d")
+            ));
+        }
+        list.push_str(&format!(
+            "<a href=\"{}\">{}</a>",
+            config.page_href(&page.path, prefix),
+            escape_html(&page.title)
+        ));
+        if page.draft {
+            list.push_str(" <span class=\"draft\">DRAFT</span>");
+        }
+        list.push_str("</li>");
     }
+    list.push_str("</ul>");
+    list
+}
 
-    format!(
-        "<body>\
-        <div class=\"navbar\">\
-        <a href=\"{prefix}index.html\" class=\"{home}\">Home</a>\
-        <a href=\"{prefix}posts/posts.html\" class=\"{post}\">Posts</a>\
-        <a href=\"{prefix}notes/notes.html\" class=\"{note}\">Notes</a>\
-        <a href=\"{prefix}links/links.html\" class=\"{link}\">Links</a>\
-        </div>
-        "
-    )
+/// Lists every page under `tag`, with the pages' paths taken relative to `prefix`.
+pub fn format_tag_page(tag: &Tag, slug: &str, prefix: &str, config: &Config) -> String {
+    let mut list = format_page_list(&tag.pages, prefix, config);
+    list.push_str("<p>");
+    if config.feeds.contains(&FeedFormat::Rss) {
+        list.push_str(&format!(
+            "<a href=\"{}\">RSS feed for {}</a> - ",
+            config.url(&format!("tags/{slug}.xml")),
+            escape_html(&tag.name)
+        ));
+    }
+    list.push_str(&format!(
+        "<a href=\"{}\">All tags</a></p>",
+        config.page_href(Path::new("tags/index.html"), prefix)
+    ));
+    list
 }
 
-pub fn format_footer() -> String {
-    "</article></body></html>".to_string()
+/// Lists every tag, sized according to how many pages use it, linking to the tags' pages
+/// relative to `prefix`.
+pub fn format_tag_cloud(tags: &BTreeMap<String, Tag>, prefix: &str, config: &Config) -> String {
+    let max = tags.values().map(|tag| tag.pages.len()).max().unwrap_or(1);
+
+    let mut cloud = String::with_capacity(1024);
+    cloud.push_str("<p class=\"tag-cloud\">");
+    for (slug, tag) in tags.iter() {
+        // Scale from 100% for the least used tags up to 200% for the most used one
+        let size = 100 + 100 * (tag.pages.len() - 1) / max.max(2).saturating_sub(1);
+        cloud.push_str(&format!(
+            "<a href=\"{}\" class=\"tag\" style=\"font-size: {size}%\">{} ({})</a> ",
+            config.page_href(Path::new(&format!("tags/{slug}.html")), prefix),
+            escape_html(&tag.name),
+            tag.pages.len()
+        ));
+    }
+    cloud.push_str("</p>");
+    cloud
 }
 
-pub fn format_metadata(metadata: &Metadata) -> String {
-    let mut title = format!(
-        "<article id=\"post\">\
-        <div class=\"stack\">\
-        <div class=\"heading-container\">\
-        <div class=\"break\"></div>\
-        <h1>{}</h1>\
-        <div class=\"break\"></div>\
-        </div>",
-        metadata.title
-    );
-    if let Some(date) = &metadata.date {
-        let date = format!(
-            "<span class=\"date\">Published: {}</span>",
-            date.format("%Y-%m-%d")
-        );
-        title.push_str(&date);
+/// Escapes `s` to be written as html text or inside a quoted attribute.
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
     }
-    title.push_str("</div>");
-    title
+    escaped
 }
@@ -9,8 +9,41 @@ pub mod builder;
 pub use rss::*;
 pub mod rss;
 
+pub use feed::*;
+pub mod feed;
+
+pub use feed_validator::*;
+pub mod feed_validator;
+
 pub use link::*;
 pub mod link;
 
+pub use url::*;
+pub mod url;
+
 pub use error::*;
 pub mod error;
+
+pub use diagnostic::*;
+pub mod diagnostic;
+
+pub use tags::*;
+pub mod tags;
+
+pub use config::*;
+pub mod config;
+
+pub use template::*;
+pub mod template;
+
+pub use external::*;
+pub mod external;
+
+pub use manifest::*;
+pub mod manifest;
+
+pub use serve::*;
+pub mod serve;
+
+pub use log::{Verbosity, set_verbosity, verbosity};
+pub mod log;
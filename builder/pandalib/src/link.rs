@@ -1,9 +1,16 @@
+use crate::escape_html;
+use html5gum::{DefaultEmitter, Token, Tokenizer};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::ops::Range;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
 pub struct Link {
     pub link: String,
     pub file: PathBuf,
+    /// Where the link was written, when it comes from a page's markdown.
+    pub position: Option<LinkPosition>,
 }
 
 #[derive(Debug, Clone)]
@@ -11,3 +18,263 @@ pub struct RelativeLink(pub Link);
 
 #[derive(Debug, Clone)]
 pub struct UrlLink(pub Link);
+
+/// Position of a link inside the markdown file it was written in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkPosition {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub length: usize, // Length of the link's markdown, when written on a single line
+}
+
+impl std::fmt::Display for LinkPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file.display(), self.line, self.column)
+    }
+}
+
+/// Link found while rendering markdown, before knowing which file it ends up in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarkdownLink {
+    pub url: String,
+    pub line: usize,
+    pub column: usize,
+    pub length: usize, // Length of the link's markdown, when written on a single line
+}
+
+/// Returns every url referenced by `html`, in document order: `href`s (anchors, `<link>`s...),
+/// `src`s and each candidate of a `srcset`. Urls which can't point to a file, like `mailto:`
+/// or `data:`, are left out.
+pub fn extract_links(html: &str) -> Vec<String> {
+    let mut links = Vec::new();
+
+    for Ok(token) in Tokenizer::new(html) {
+        let Token::StartTag(tag) = token else {
+            continue;
+        };
+
+        for (name, value) in tag.attributes.iter() {
+            let value = String::from_utf8_lossy(value);
+            match name.as_slice() {
+                b"href" | b"src" | b"poster" => links.push(value.trim().to_owned()),
+                b"srcset" => links.extend(
+                    value
+                        .split(',')
+                        .filter_map(|candidate| candidate.split_whitespace().next())
+                        .map(str::to_owned),
+                ),
+                _ => (),
+            }
+        }
+    }
+
+    links.retain(|link| is_followable(link));
+    links
+}
+
+/// Returns every fragment `html` can be linked to with: the ids of its elements, and the names
+/// of its `<a name>` anchors, which browsers scroll to as well.
+pub fn extract_ids(html: &str) -> BTreeSet<String> {
+    let mut ids = BTreeSet::new();
+
+    for Ok(token) in Tokenizer::new(html) {
+        let Token::StartTag(tag) = token else {
+            continue;
+        };
+
+        for (name, value) in tag.attributes.iter() {
+            let is_id = match name.as_slice() {
+                b"id" => true,
+                b"name" => tag.name.as_slice() == b"a",
+                _ => false,
+            };
+            if is_id && !value.is_empty() {
+                ids.insert(String::from_utf8_lossy(value).into_owned());
+            }
+        }
+    }
+
+    ids
+}
+
+/// Whether `link` points to something on the web, as opposed to a file of this site.
+pub fn is_external(link: &str) -> bool {
+    link.starts_with("http://") || link.starts_with("https://")
+}
+
+fn is_followable(link: &str) -> bool {
+    let scheme = link
+        .split_once(':')
+        .map(|(scheme, _)| scheme.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    !link.is_empty() && !matches!(scheme.as_str(), "mailto" | "tel" | "data" | "javascript")
+}
+
+/// Rewrites every relative url of `html` into an absolute one, taking urls relative to `base`,
+/// so that the html still works outside of the page it was rendered for, like in a feed. The
+/// same attributes as [`extract_links`] are rewritten, however they're quoted.
+pub fn absolute_links(html: &str, base: &str) -> String {
+    // Whole attributes to rewrite, as byte ranges of `html`
+    let mut replacements: Vec<(Range<usize>, String)> = Vec::new();
+
+    let tokenizer = Tokenizer::new_with_emitter(html, DefaultEmitter::new_with_span());
+    for Ok(token) in tokenizer {
+        let Token::StartTag(tag) = token else {
+            continue;
+        };
+
+        for (name, value) in tag.attributes.iter() {
+            let url = String::from_utf8_lossy(value);
+            let absolute = match name.as_slice() {
+                // An empty url is the page itself, which isn't worth rewriting
+                _ if url.trim().is_empty() => continue,
+                b"href" | b"src" | b"poster" => resolve_url(base, url.trim()),
+                b"srcset" => url
+                    .split(',')
+                    .map(|candidate| {
+                        let candidate = candidate.trim();
+                        match candidate.split_once(char::is_whitespace) {
+                            Some((url, descriptor)) => {
+                                format!("{} {}", resolve_url(base, url), descriptor.trim())
+                            }
+                            None => resolve_url(base, candidate),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                _ => continue,
+            };
+
+            // The span of an unquoted value also takes the character which ends it
+            let Some(attribute) = html.get(value.span.start..value.span.end) else {
+                continue;
+            };
+            let length = attribute
+                .trim_end_matches(|c: char| c == '>' || c.is_ascii_whitespace())
+                .len();
+            let name = String::from_utf8_lossy(name);
+            replacements.push((
+                value.span.start..value.span.start + length,
+                format!("{}=\"{}\"", name, escape_html(&absolute)),
+            ));
+        }
+    }
+
+    replacements.sort_by_key(|(span, _)| span.start);
+
+    let mut absolute = String::with_capacity(html.len());
+    let mut written = 0;
+    for (span, attribute) in replacements {
+        if span.start < written {
+            continue;
+        }
+        absolute.push_str(&html[written..span.start]);
+        absolute.push_str(&attribute);
+        written = span.end;
+    }
+
+    absolute.push_str(&html[written..]);
+    absolute
+}
+
+/// `url` taken relative to the absolute url `base`, like a browser would.
+pub fn resolve_url(base: &str, url: &str) -> String {
+    let has_scheme = url
+        .split_once(':')
+        .is_some_and(|(scheme, _)| !scheme.is_empty() && !scheme.contains(['/', '?', '#']));
+    if has_scheme || url.starts_with("//") {
+        return url.to_owned();
+    }
+
+    let base = base.split('#').next().unwrap_or_default();
+    if url.is_empty() {
+        return base.to_owned();
+    }
+    if url.starts_with('#') {
+        return format!("{base}{url}");
+    }
+
+    let base = base.split('?').next().unwrap_or_default();
+    if url.starts_with('?') {
+        return format!("{base}{url}");
+    }
+
+    let origin_end = base
+        .find("://")
+        .map(|scheme| {
+            let host = scheme + 3;
+            base[host..]
+                .find('/')
+                .map_or(base.len(), |path| host + path)
+        })
+        .unwrap_or(0);
+    let (origin, path) = base.split_at(origin_end);
+
+    if url.starts_with('/') {
+        return format!("{origin}{url}");
+    }
+
+    // Segments of the base's directory, then of the url, resolving `.` and `..`
+    let mut segments: Vec<&str> = path.split('/').skip(1).collect();
+    segments.pop();
+    let (url_path, suffix) = url.split_at(url.find(['?', '#']).unwrap_or(url.len()));
+    let mut url_segments = url_path.split('/').peekable();
+    while let Some(segment) = url_segments.next() {
+        let last = url_segments.peek().is_none();
+        match segment {
+            "." if last => segments.push(""),
+            "." => (),
+            ".." => {
+                segments.pop();
+                if last {
+                    segments.push("");
+                }
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    format!("{origin}/{}{suffix}", segments.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "https://example.com/posts/foo.html";
+
+    #[test]
+    fn absolute_links_rewrites_every_quoting() {
+        let html =
+            "<a href=\"../a.html\">a</a><a href='b.html?x=1&amp;y=2'>b</a><a href=c.html>c</a>";
+        assert_eq!(
+            absolute_links(html, BASE),
+            "<a href=\"https://example.com/a.html\">a</a>\
+             <a href=\"https://example.com/posts/b.html?x=1&amp;y=2\">b</a>\
+             <a href=\"https://example.com/posts/c.html\">c</a>"
+        );
+    }
+
+    #[test]
+    fn absolute_links_rewrites_srcset_candidates() {
+        let html = "<img src=\"/d.png\" srcset=\"e.png 1x, f.png 2x\" data-src=\"g.png\">";
+        assert_eq!(
+            absolute_links(html, BASE),
+            "<img src=\"https://example.com/d.png\" \
+             srcset=\"https://example.com/posts/e.png 1x, https://example.com/posts/f.png 2x\" \
+             data-src=\"g.png\">"
+        );
+    }
+
+    #[test]
+    fn absolute_links_keeps_absolute_urls() {
+        let html = "<p>é <a href=\"https://example.org/\">x</a> <a href=\"#top\">y</a></p>";
+        assert_eq!(
+            absolute_links(html, BASE),
+            "<p>é <a href=\"https://example.org/\">x</a> \
+             <a href=\"https://example.com/posts/foo.html#top\">y</a></p>"
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much is printed while building.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only errors.
+    Quiet,
+    /// Errors, warnings and the steps of the build.
+    Normal,
+    /// Everything, including what's done to every file.
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+/// Prints a step of the build, unless quiet.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::verbosity() >= $crate::Verbosity::Normal {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Prints a detail of the build, only when verbose.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::verbosity() >= $crate::Verbosity::Verbose {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Prints a problem which doesn't fail the build, unless quiet.
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        if $crate::verbosity() >= $crate::Verbosity::Normal {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use {debug, info, warning};
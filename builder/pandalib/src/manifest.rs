@@ -0,0 +1,101 @@
+use crate::{Config, MarkdownLink};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// What every page and asset of a build was made from, kept next to the output directory so
+/// the next build can reuse the outputs whose inputs didn't change, without being published
+/// along with the site.
+///
+/// Pages depend on panda's own code, the config and the templates as well as on their source,
+/// so they're only reused if none of those changed. Indexes, tag pages and feeds depend on every
+/// page, and are cheap to generate, so they're always rebuilt.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub builder: String,   // Hash of the sources panda was built from
+    pub config: String,    // Hash of the settings pages are rendered with
+    pub templates: String, // Hash of every template
+    pub outputs: BTreeMap<PathBuf, ManifestEntry>, // Key is the path relative to the output dir
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub input: String, // Hash of the source file
+    /// Links written in the page's markdown, to point at them without rendering it again.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<MarkdownLink>,
+    /// What the page's feed items carry, for pages which are in a feed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feed: Option<FeedContent>,
+}
+
+/// The part of a page's rendered markdown that feeds publish.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FeedContent {
+    pub html: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+}
+
+impl Manifest {
+    /// Empty manifest for a build of `config`.
+    pub fn new(config: &Config) -> Self {
+        // Checking links doesn't change what's rendered
+        let mut rendered = config.clone();
+        rendered.links = Default::default();
+        rendered.external_links = Default::default();
+
+        Self {
+            builder: env!("PANDA_SOURCE_HASH").to_owned(),
+            config: hash(&serde_json::to_vec(&rendered).unwrap_or_default()),
+            templates: hash_dir(&config.templates),
+            outputs: BTreeMap::new(),
+        }
+    }
+
+    /// Manifest saved at `path`. A missing or unreadable one is empty, so that nothing is
+    /// reused.
+    pub fn load(path: &Path) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let contents = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(path, contents)
+    }
+
+    /// Whether pages of the `previous` build were rendered the same way as this one's are.
+    pub fn renders_like(&self, previous: &Manifest) -> bool {
+        self.builder == previous.builder
+            && self.config == previous.config
+            && self.templates == previous.templates
+    }
+}
+
+/// Hex encoded sha256 of `bytes`.
+pub fn hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Hash of the path and contents of every file inside `dir`.
+fn hash_dir(dir: &Path) -> String {
+    let mut hasher = Sha256::new();
+    for entry in WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+    {
+        let relative = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(std::fs::read(entry.path()).unwrap_or_default());
+        hasher.update([0]);
+    }
+    format!("{:x}", hasher.finalize())
+}
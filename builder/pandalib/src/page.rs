@@ -1,6 +1,8 @@
 use std::path::{Path, PathBuf};
 
-use chrono::FixedOffset;
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveTime};
+
+use crate::{BuildError, Category, Config, FrontMatterError, tag_uri};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PageKind {
@@ -8,20 +10,21 @@ pub enum PageKind {
     Article,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Category {
-    Home,
-    Post,
-    Note,
-    Link,
-    Rambling,
-}
-
 #[derive(Debug, Clone)]
 pub struct Metadata {
     pub title: String,
     pub date: Option<chrono::DateTime<FixedOffset>>,
     pub draft: bool,
+    pub tags: Vec<String>,
+    /// Short description of the page for feeds, from either `summary` or `description`.
+    pub summary: Option<String>,
+    /// Author of the page, when not the site's.
+    pub author: Option<String>,
+    /// Identifies the page in feeds instead of its date and path, to keep readers from
+    /// showing it again when the page is moved.
+    pub guid: Option<String>,
+    /// Any front matter keys not listed above.
+    pub extra: toml::Table,
 }
 
 impl Default for Metadata {
@@ -30,6 +33,11 @@ impl Default for Metadata {
             title: "Homepage".to_string(),
             date: None,
             draft: false,
+            tags: Vec::new(),
+            summary: None,
+            author: None,
+            guid: None,
+            extra: toml::Table::new(),
         }
     }
 }
@@ -38,142 +46,415 @@ impl Default for Metadata {
 pub struct Page {
     pub content: String,
     pub kind: PageKind,
-    pub category: Category,
+    pub category: Option<Category>, // None for pages outside of any configured category
     pub metadata: Metadata,
     pub path: PathBuf, // path relative to root
 }
 
 impl Page {
-    pub fn new(path: &Path, link: &Path) -> Self {
-        let content = std::fs::read_to_string(path)
-            .unwrap_or_else(|_| panic!("Couldn't read file: {:?}", path));
-
-        let kind = if path.ends_with("index.md")
-            || path.ends_with("posts.md")
-            || path.ends_with("notes.md")
-            || path.ends_with("links.md")
-            || path.ends_with("ramblings.md")
-        {
+    pub fn new(path: &Path, link: &Path, config: &Config) -> Result<Self, BuildError> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| BuildError::Read(path.to_path_buf(), e))?;
+
+        let category = config.category_of(link).cloned();
+
+        let is_category_index = category
+            .as_ref()
+            .is_some_and(|category| !category.dir.is_empty() && link == category.index_path());
+
+        let kind = if link.ends_with("index.html") || is_category_index {
             PageKind::Index
         } else {
             PageKind::Article
         };
 
-        let category = if let Some(path) = path.parent() {
-            if path.ends_with("posts/") {
-                Category::Post
-            } else if path.ends_with("notes/") {
-                Category::Note
-            } else if path.ends_with("ramblings/") {
-                Category::Rambling
-            } else if path.ends_with("src/") {
-                Category::Home
-            } else if path.ends_with("links/") {
-                Category::Link
-            } else {
-                unreachable!()
-            }
-        } else {
-            Category::Home
-        };
+        let metadata = parse_header(&content, path).map_err(BuildError::FrontMatter)?;
 
-        let metadata = match kind {
-            PageKind::Article => parse_header(&content),
-            PageKind::Index => {
-                let title = match category {
-                    Category::Home => "Homepage",
-                    Category::Post => "Posts",
-                    Category::Note => "Notes",
-                    Category::Link => "Links",
-                    Category::Rambling => "Ramblings",
-                };
-                Metadata {
-                    title: title.to_string(),
-                    date: None,
-                    draft: false,
-                }
-            }
-        };
-
-        Self {
+        Ok(Self {
             kind,
             category,
             content,
             metadata,
             path: link.to_path_buf(),
-        }
+        })
     }
 
-    pub fn link(&self, root: &Path) -> String {
-        let link = "https://eduardorittner.github.io/".to_owned();
+    pub fn link(&self, config: &Config) -> String {
+        config.page_url(&self.path)
+    }
 
-        let addon = self
-            .path
-            .strip_prefix(root)
-            .unwrap_or_else(|_| panic!("Couldn't find root: {root:?} in file: {:?}", self.path))
-            .to_string_lossy()
-            .replace(".md", ".html");
+    /// Whether the page goes in the rss feed. Drafts never do, even when previewed.
+    pub fn in_rss(&self) -> bool {
+        self.kind == PageKind::Article
+            && !self.metadata.draft
+            && self.category.as_ref().is_some_and(|c| c.rss)
+    }
+
+    /// Whether the page goes in any feed, the main one or the feed of one of its tags.
+    pub fn in_feeds(&self) -> bool {
+        self.in_rss() || (!self.metadata.draft && !self.metadata.tags.is_empty())
+    }
 
-        link + &addon
+    /// Line of the page's source where `key` is set in its front matter.
+    pub fn key_line(&self, key: &str) -> usize {
+        let header = FrontMatterFormat::detect(&self.content)
+            .and_then(|format| front_matter(&self.content, format.delimiter()))
+            .unwrap_or_default();
+        key_line(header, key)
     }
 
-    pub fn is_post(&self) -> bool {
-        self.category == Category::Post && self.kind == PageKind::Article
+    /// Whether the page gets a table of contents.
+    pub fn has_toc(&self) -> bool {
+        self.kind == PageKind::Article && self.category.as_ref().is_some_and(|c| c.toc)
     }
 }
 
-fn parse_header(contents: &str) -> Metadata {
-    let mut metadata = Metadata::default();
-    let header_start = contents.strip_prefix("+++\n");
+/// Creates a draft at `path` inside the source directory, with its front matter ready to be
+/// filled in, and returns the new file's path. `.md` is added to `path` if it has no extension,
+/// and the title defaults to the file's name. Its guid is written down too, so that feeds keep
+/// it if the draft is renamed later.
+pub fn create_page(
+    config: &Config,
+    path: &Path,
+    title: Option<&str>,
+) -> Result<PathBuf, BuildError> {
+    let mut file = config.source.join(path);
+    if file.extension().is_none() {
+        file.set_extension("md");
+    }
+
+    if file.exists() {
+        return Err(BuildError::IoError(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", file.display()),
+        )));
+    }
 
-    let header_start = match header_start {
-        None => return metadata,
-        Some(a) => a,
+    let title = match title {
+        Some(title) => title.to_owned(),
+        None => file
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .replace(['-', '_'], " "),
     };
+    let date = Local::now().fixed_offset();
+    let source = file.strip_prefix(&config.source).unwrap_or(&file);
+    let contents = format!(
+        "+++\ntitle = {}\ndate = {}\nguid = {}\ndraft = true\n+++\n\n",
+        toml::Value::String(title),
+        date.format("%Y-%m-%dT%H:%M:%S%:z"),
+        toml::Value::String(tag_uri(config, &date, source)),
+    );
 
-    let title_start = header_start
-        .strip_prefix("title = ")
-        .expect("Expected 'title = ' after header delimiter");
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir).map_err(BuildError::IoError)?;
+    }
+    std::fs::write(&file, contents).map_err(BuildError::IoError)?;
 
-    let (title, rest) = title_start
-        .split_once('\n')
-        .expect("Expected '\n' after title parameter");
+    Ok(file)
+}
 
-    let title = strip_string_delim(title);
-    metadata.title = title.to_string();
+/// What index pages (category and tag listings) need to know about a page.
+#[derive(Debug, Clone)]
+pub struct PageSummary {
+    pub title: String,
+    pub date: Option<chrono::DateTime<FixedOffset>>,
+    pub path: PathBuf, // path relative to dest
+    pub draft: bool,   // only rendered when previewing drafts
+    pub item: rss::Item,
+}
 
-    let date_start = rest
-        .strip_prefix("date = ")
-        .expect("Expected 'date = ' after title declaration");
+impl PageSummary {
+    pub fn new(page: &Page, item: rss::Item) -> Self {
+        Self {
+            title: page.metadata.title.clone(),
+            date: page.metadata.date,
+            path: page.path.clone(),
+            draft: page.metadata.draft,
+            item,
+        }
+    }
+}
 
-    let (date, rest) = date_start
-        .split_once('\n')
-        .expect("Expected '\n' after date parameter");
+/// Sorts pages from newest to oldest, undated pages last.
+pub fn sort_by_date(pages: &mut [PageSummary]) {
+    pages.sort_by(|a, b| {
+        b.date
+            .cmp(&a.date)
+            .then_with(|| a.title.cmp(&b.title))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+}
 
-    if let Ok(date) = chrono::DateTime::parse_from_rfc3339(date) {
-        metadata.date = Some(date);
-    } else {
-        println!("invalid date: {date}")
+/// Format of a page's front matter, detected from its opening delimiter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontMatterFormat {
+    /// `+++` delimited TOML, as used by Hugo and Zola.
+    Toml,
+    /// `---` delimited YAML, as used by Jekyll and older Hugo sites.
+    Yaml,
+}
+
+impl FrontMatterFormat {
+    pub fn delimiter(self) -> &'static str {
+        match self {
+            FrontMatterFormat::Toml => "+++",
+            FrontMatterFormat::Yaml => "---",
+        }
     }
 
-    let (draft, _) = if let Some(draft) = rest.strip_prefix("draft = ") {
-        let (draft, _) = draft
-            .split_once("\n")
-            .expect("Expected newline after 'draft'");
-        (draft == "true", ())
-    } else {
-        (false, ())
+    /// Returns the front matter format of `contents`, if it starts with a complete front matter
+    /// block.
+    pub fn detect(contents: &str) -> Option<Self> {
+        [FrontMatterFormat::Toml, FrontMatterFormat::Yaml]
+            .into_iter()
+            .find(|format| front_matter(contents, format.delimiter()).is_some())
+    }
+}
+
+/// Parses the TOML or YAML front matter at the start of `contents`.
+///
+/// Files without front matter get the default metadata.
+fn parse_header(contents: &str, path: &Path) -> Result<Metadata, FrontMatterError> {
+    let Some(format) = FrontMatterFormat::detect(contents) else {
+        return Ok(Metadata::default());
     };
+    let header = front_matter(contents, format.delimiter()).unwrap_or_default();
 
-    metadata.draft = draft;
+    let table = match format {
+        FrontMatterFormat::Toml => parse_toml(header, path)?,
+        FrontMatterFormat::Yaml => parse_yaml(header, path)?,
+    };
+
+    Metadata::from_table(table, header, path)
+}
 
-    metadata
+fn parse_toml(header: &str, path: &Path) -> Result<toml::Table, FrontMatterError> {
+    toml::from_str(header).map_err(|e| {
+        let line = e
+            .span()
+            .map(|span| line_of(header, span.start))
+            .unwrap_or(FRONT_MATTER_LINE);
+        FrontMatterError::new(path, line, e.message())
+    })
 }
 
-fn strip_string_delim(s: &str) -> &str {
-    if let Some(s) = s.strip_prefix("'") {
-        s.strip_suffix("'").unwrap()
-    } else {
-        s.strip_prefix("\"").unwrap().strip_suffix("\"").unwrap()
+/// Parses YAML front matter into the same table TOML front matter produces, so both go through
+/// [`Metadata::from_table`].
+fn parse_yaml(header: &str, path: &Path) -> Result<toml::Table, FrontMatterError> {
+    let value: serde_yaml::Value = serde_yaml::from_str(header).map_err(|e| {
+        let line = e
+            .location()
+            .map(|location| FRONT_MATTER_LINE + location.line() - 1)
+            .unwrap_or(FRONT_MATTER_LINE);
+        FrontMatterError::new(path, line, e.to_string())
+    })?;
+
+    match yaml_to_toml(value) {
+        // An empty front matter block is parsed as null
+        None => Ok(toml::Table::new()),
+        Some(toml::Value::Table(table)) => Ok(table),
+        Some(other) => Err(FrontMatterError::new(
+            path,
+            FRONT_MATTER_LINE,
+            format!(
+                "expected a mapping as front matter, found {}",
+                other.type_str()
+            ),
+        )),
+    }
+}
+
+/// Converts a YAML value to its TOML equivalent. TOML has no null, so null values (and map
+/// entries holding them) are dropped.
+fn yaml_to_toml(value: serde_yaml::Value) -> Option<toml::Value> {
+    use serde_yaml::Value;
+
+    let value = match value {
+        Value::Null => return None,
+        Value::Bool(b) => toml::Value::Boolean(b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => toml::Value::Integer(i),
+            None => toml::Value::Float(n.as_f64()?),
+        },
+        Value::String(s) => toml::Value::String(s),
+        Value::Sequence(seq) => {
+            toml::Value::Array(seq.into_iter().filter_map(yaml_to_toml).collect())
+        }
+        Value::Mapping(map) => toml::Value::Table(
+            map.into_iter()
+                .filter_map(|(key, value)| {
+                    let key = match key {
+                        Value::String(s) => s,
+                        Value::Bool(b) => b.to_string(),
+                        Value::Number(n) => n.to_string(),
+                        _ => return None,
+                    };
+                    Some((key, yaml_to_toml(value)?))
+                })
+                .collect(),
+        ),
+        Value::Tagged(tagged) => return yaml_to_toml(tagged.value),
+    };
+
+    Some(value)
+}
+
+/// The line where the front matter contents start, right after the opening delimiter.
+const FRONT_MATTER_LINE: usize = 2;
+
+/// Returns the text between the opening and closing `delimiter` lines, if `contents` starts
+/// with one.
+fn front_matter<'a>(contents: &'a str, delimiter: &str) -> Option<&'a str> {
+    let rest = contents.strip_prefix(delimiter)?;
+    let rest = rest
+        .strip_prefix('\n')
+        .or_else(|| rest.strip_prefix("\r\n"))?;
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == delimiter {
+            return Some(&rest[..offset]);
+        }
+        offset += line.len();
+    }
+
+    None
+}
+
+/// Line number in the original file of byte `offset` inside the front matter.
+fn line_of(header: &str, offset: usize) -> usize {
+    FRONT_MATTER_LINE + header[..offset.min(header.len())].matches('\n').count()
+}
+
+/// Line number in the original file where `key` is defined inside the front matter.
+fn key_line(header: &str, key: &str) -> usize {
+    let mut offset = 0;
+    for line in header.split_inclusive('\n') {
+        if line
+            .trim_start()
+            .strip_prefix(key)
+            .is_some_and(|rest| rest.trim_start().starts_with(['=', ':']))
+        {
+            return line_of(header, offset);
+        }
+        offset += line.len();
+    }
+    FRONT_MATTER_LINE
+}
+
+/// Parses either a full RFC 3339 date, a Jekyll style `YYYY-MM-DD HH:MM:SS +ZZZZ` date or a
+/// plain `YYYY-MM-DD` date (taken as midnight UTC).
+fn parse_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Some(date);
+    }
+
+    if let Ok(date) = DateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S %z") {
+        return Some(date);
+    }
+
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    Some(date.and_time(NaiveTime::MIN).and_utc().fixed_offset())
+}
+
+impl Metadata {
+    fn from_table(
+        mut table: toml::Table,
+        header: &str,
+        path: &Path,
+    ) -> Result<Metadata, FrontMatterError> {
+        let mut metadata = Metadata::default();
+        let error = |key: &str, message: String| {
+            FrontMatterError::new(path, key_line(header, key), message)
+        };
+
+        match table.remove("title") {
+            None => (),
+            Some(toml::Value::String(title)) => metadata.title = title,
+            Some(other) => {
+                return Err(error(
+                    "title",
+                    format!("expected a string for `title`, found {}", other.type_str()),
+                ));
+            }
+        }
+
+        match table.remove("date") {
+            None => (),
+            Some(date) => {
+                let date = match date {
+                    toml::Value::String(date) => date,
+                    toml::Value::Datetime(date) => date.to_string(),
+                    other => {
+                        return Err(error(
+                            "date",
+                            format!("expected a date for `date`, found {}", other.type_str()),
+                        ));
+                    }
+                };
+
+                metadata.date = Some(
+                    parse_date(&date)
+                        .ok_or_else(|| error("date", format!("invalid date: {date}")))?,
+                );
+            }
+        }
+
+        match table.remove("draft") {
+            None => (),
+            Some(toml::Value::Boolean(draft)) => metadata.draft = draft,
+            Some(other) => {
+                return Err(error(
+                    "draft",
+                    format!("expected a boolean for `draft`, found {}", other.type_str()),
+                ));
+            }
+        }
+
+        match table.remove("tags") {
+            None => (),
+            // Jekyll allows tags as a single space separated string
+            Some(toml::Value::String(tags)) => {
+                metadata.tags = tags.split_whitespace().map(str::to_owned).collect()
+            }
+            Some(toml::Value::Array(tags)) => {
+                metadata.tags = tags
+                    .into_iter()
+                    .map(|tag| match tag {
+                        toml::Value::String(tag) => Ok(tag),
+                        other => Err(error(
+                            "tags",
+                            format!("expected a string tag, found {}", other.type_str()),
+                        )),
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            Some(other) => {
+                return Err(error(
+                    "tags",
+                    format!("expected a list for `tags`, found {}", other.type_str()),
+                ));
+            }
+        }
+
+        let mut string = |key: &str| match table.remove(key) {
+            None => Ok(None),
+            Some(toml::Value::String(value)) => Ok(Some(value)),
+            Some(other) => Err(error(
+                key,
+                format!("expected a string for `{key}`, found {}", other.type_str()),
+            )),
+        };
+
+        let description = string("description")?;
+        metadata.summary = string("summary")?.or(description);
+        metadata.author = string("author")?;
+        metadata.guid = string("guid")?;
+
+        metadata.extra = table;
+
+        Ok(metadata)
     }
 }
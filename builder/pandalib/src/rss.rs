@@ -1,29 +1,113 @@
-use crate::Page;
-use rss::{ChannelBuilder, ItemBuilder};
+use crate::builder::RenderedMarkdown;
+use crate::{Config, Page, PageSummary, absolute_links, percent_encode, sort_by_date};
+use chrono::{DateTime, FixedOffset};
+use rss::extension::dublincore::DublinCoreExtension;
+use rss::{Category, ChannelBuilder, Guid, ItemBuilder};
+use std::path::Path;
+
+/// Channel of the site holding `items`, last built when the newest of them was published so
+/// that it only changes along with them.
+pub fn new_rss(config: &Config, items: Vec<rss::Item>) -> rss::Channel {
+    let last_build_date = items
+        .iter()
+        .filter_map(|item| item.pub_date())
+        .filter_map(|date| DateTime::parse_from_rfc2822(date).ok())
+        .max()
+        .map(|date| date.to_rfc2822());
 
-pub fn new_rss(items: Vec<rss::Item>) -> rss::Channel {
     ChannelBuilder::default()
-        .title("Eduardo's blog")
-        .link("https://eduardorittner.github.io")
-        .description("My blog")
-        .language("en-us".to_owned())
+        .title(config.title.clone())
+        .link(config.base_url.clone())
+        .description(config.description.clone())
+        .language(config.language.clone())
         .docs("https://www.rssboard.org/rss-specification".to_owned())
+        .last_build_date(last_build_date)
         .items(items)
         .build()
 }
 
-pub fn new_item(page: &Page) -> rss::Item {
-    let mut link = "https://eduardorittner.github.io/".to_owned();
-    link.push_str(page.path.to_str().unwrap());
+/// Items of the feed of `pages`, newest first and no more than the config allows. Drafts are
+/// left out.
+pub fn feed_items(pages: &[PageSummary], config: &Config) -> Vec<rss::Item> {
+    let mut pages = pages.to_vec();
+    pages.retain(|page| !page.draft);
+    sort_by_date(&mut pages);
+
+    pages
+        .into_iter()
+        .take(config.feed_limit.unwrap_or(usize::MAX))
+        .map(|page| page.item)
+        .collect()
+}
+
+/// Feed item of `page`, carrying its whole rendered `content`.
+pub fn new_item(page: &Page, config: &Config, content: &RenderedMarkdown) -> rss::Item {
+    let link = config.page_url(&page.path);
+    let metadata = &page.metadata;
 
     let mut item = ItemBuilder::default();
 
-    item.title(page.metadata.title.clone())
-        .link(link.to_owned());
+    item.title(metadata.title.clone())
+        .link(link.to_owned())
+        .guid(guid(page, config))
+        .description(metadata.summary.clone().or(content.summary.clone()))
+        .content(absolute_links(&content.html, &link))
+        .categories(
+            metadata
+                .tags
+                .iter()
+                .map(|tag| Category {
+                    name: tag.clone(),
+                    domain: None,
+                })
+                .collect::<Vec<_>>(),
+        );
+
+    // rss wants an email as `author`, names go in `dc:creator`
+    if let Some(author) = metadata.author.as_ref().or(config.author.as_ref()) {
+        item.dublin_core_ext(DublinCoreExtension {
+            creators: vec![author.clone()],
+            ..Default::default()
+        });
+    }
 
-    if let Some(date) = &page.metadata.date {
+    if let Some(date) = &metadata.date {
         item.pub_date(date.to_rfc2822().clone()).build()
     } else {
         item.build()
     }
 }
+
+/// Guid of `page`: the guid its front matter sets, which `panda new` writes so that it stays
+/// the same when the page is renamed or moved. Pages without one get the [`tag_uri`] their
+/// date and source give, or the url they have without pretty urls when undated.
+fn guid(page: &Page, config: &Config) -> Guid {
+    let metadata = &page.metadata;
+    let value = match (&metadata.guid, &metadata.date) {
+        (Some(guid), _) => guid.clone(),
+        (None, Some(date)) => tag_uri(config, date, &page.path),
+        (None, None) => config.url(&page.path.to_string_lossy().replace('\\', "/")),
+    };
+
+    Guid {
+        value,
+        permalink: false,
+    }
+}
+
+/// `tag:` uri (see <https://www.rfc-editor.org/rfc/rfc4151>) naming the page first written at
+/// `path`, relative to the source directory, on `date`.
+pub fn tag_uri(config: &Config, date: &DateTime<FixedOffset>, path: &Path) -> String {
+    let authority = config
+        .base_url
+        .split_once("://")
+        .map_or("", |(_, rest)| rest);
+    let host = authority.split(['/', ':']).next().unwrap_or_default();
+    let source = path.with_extension("");
+    format!(
+        "tag:{},{}:{}",
+        host,
+        date.format("%Y-%m-%d"),
+        percent_encode(&source.to_string_lossy().replace('\\', "/"))
+    )
+}
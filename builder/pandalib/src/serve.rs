@@ -0,0 +1,262 @@
+use crate::log::info;
+use crate::{BuildError, Config, builder::Site, percent_decode};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+/// Path pages listen on to know when to reload.
+pub const RELOAD_PATH: &str = "/__panda/reload";
+
+/// Injected into every page the server sends, never written to the output directory.
+const RELOAD_SCRIPT: &str = "<script>new EventSource(\"/__panda/reload\").onmessage = \
+    () => location.reload();</script>";
+
+/// How often sources are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Pages waiting for the next build to reload.
+type Clients = Arc<Mutex<Vec<TcpStream>>>;
+
+/// Builds the site, serves its output at `address` and rebuilds it whenever its sources,
+/// templates or the config at `config_path` change, reloading the pages open in a browser.
+/// `overrides` is applied to the config every time it's loaded.
+///
+/// Builds are incremental through the output's manifest, so only changed pages are rendered
+/// again. A failing build is reported and the previous output kept being served.
+pub fn serve(
+    config_path: &Path,
+    mut config: Config,
+    address: &str,
+    overrides: impl Fn(&mut Config) + Send + 'static,
+) -> Result<(), BuildError> {
+    overrides(&mut config);
+    let listener = TcpListener::bind(address).map_err(BuildError::IoError)?;
+    let output = config.output.clone();
+    let clients = Clients::default();
+
+    build(&config);
+    info!("Serving {} on http://{}", output.display(), address);
+
+    {
+        let clients = clients.clone();
+        let config_path = config_path.to_owned();
+        std::thread::spawn(move || watch(&config_path, config, overrides, &clients));
+    }
+
+    for stream in listener.incoming().filter_map(|stream| stream.ok()) {
+        let output = output.clone();
+        let clients = clients.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = respond(stream, &output, &clients) {
+                eprintln!("Couldn't answer request: {}", e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn build(config: &Config) -> bool {
+    match Site::build(config.clone()) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("{}", e);
+            false
+        }
+    }
+}
+
+/// Rebuilds the site every time one of its inputs changes, for as long as the server runs.
+fn watch(
+    config_path: &Path,
+    mut config: Config,
+    overrides: impl Fn(&mut Config),
+    clients: &Clients,
+) {
+    let mut last = snapshot(config_path, &config);
+
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+
+        if snapshot(config_path, &config) == last {
+            continue;
+        }
+        // Editors often write a file in several steps
+        std::thread::sleep(POLL_INTERVAL);
+        let previous = std::mem::replace(&mut last, snapshot(config_path, &config));
+
+        if previous.get(config_path) != last.get(config_path) {
+            match Config::load(config_path) {
+                Ok(reloaded) => {
+                    config = reloaded;
+                    overrides(&mut config);
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    continue;
+                }
+            }
+        }
+
+        if build(&config) {
+            reload(clients);
+        }
+    }
+}
+
+/// Modification time and size of every input of the site.
+fn snapshot(config_path: &Path, config: &Config) -> BTreeMap<PathBuf, (SystemTime, u64)> {
+    let roots = [config_path, &config.source, &config.templates];
+
+    roots
+        .into_iter()
+        .flat_map(WalkDir::new)
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let modified = metadata.modified().ok()?;
+            Some((entry.into_path(), (modified, metadata.len())))
+        })
+        .collect()
+}
+
+/// Tells every open page to reload, forgetting the ones which were closed.
+fn reload(clients: &Clients) {
+    clients
+        .lock()
+        .unwrap()
+        .retain_mut(|client| client.write_all(b"data: reload\n\n").is_ok());
+}
+
+fn respond(mut stream: TcpStream, output: &Path, clients: &Clients) -> std::io::Result<()> {
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(stream.try_clone()?);
+    reader.read_line(&mut request_line)?;
+
+    // Headers aren't needed, but have to be read before answering
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or("/"),
+    );
+
+    if method != "GET" && method != "HEAD" {
+        return send(&mut stream, "405 Method Not Allowed", "text/plain", b"");
+    }
+
+    if target == RELOAD_PATH {
+        stream.write_all(
+            b"HTTP/1.1 200 OK\r\n\
+            Content-Type: text/event-stream\r\n\
+            Cache-Control: no-cache\r\n\r\n",
+        )?;
+        clients.lock().unwrap().push(stream);
+        return Ok(());
+    }
+
+    let Some(path) = resolve(output, target) else {
+        return send(&mut stream, "404 Not Found", "text/plain", b"Not found");
+    };
+
+    let body = std::fs::read(&path)?;
+    let content_type = content_type(&path);
+    let body = if content_type.starts_with("text/html") {
+        inject_reload_script(body)
+    } else {
+        body
+    };
+
+    if method == "HEAD" {
+        return write_head(&mut stream, "200 OK", content_type, body.len());
+    }
+    send(&mut stream, "200 OK", content_type, &body)
+}
+
+fn send(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> std::io::Result<()> {
+    write_head(stream, status, content_type, body.len())?;
+    stream.write_all(body)
+}
+
+fn write_head(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    length: usize,
+) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n\
+        Content-Type: {}\r\n\
+        Content-Length: {}\r\n\
+        Cache-Control: no-cache\r\n\
+        Connection: close\r\n\r\n",
+        status, content_type, length
+    )
+}
+
+/// File of `output` which the request `target` asks for, like a static host would serve it:
+/// directories serve their `index.html`, and the `.html` extension can be left out.
+fn resolve(output: &Path, target: &str) -> Option<PathBuf> {
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    let path = percent_decode(path.trim_start_matches('/'))?;
+    let path = Path::new(&path);
+
+    // Never serve anything outside of the output directory
+    if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+
+    let path = output.join(path);
+    let candidates = [
+        path.clone(),
+        path.join("index.html"),
+        path.with_extension("html"),
+    ];
+    candidates.into_iter().find(|path| path.is_file())
+}
+
+fn inject_reload_script(html: Vec<u8>) -> Vec<u8> {
+    let html = String::from_utf8_lossy(&html);
+    match html.rfind("</body>") {
+        Some(end) => format!("{}{}{}", &html[..end], RELOAD_SCRIPT, &html[end..]),
+        None => format!("{}{}", html, RELOAD_SCRIPT),
+    }
+    .into_bytes()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+    {
+        "html" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff2" => "font/woff2",
+        "txt" => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
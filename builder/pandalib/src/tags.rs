@@ -0,0 +1,99 @@
+use crate::{PageSummary, sort_by_date};
+
+/// Turns a tag into the name used for its page and feed, e.g. "Rust SIMD" becomes "rust-simd".
+///
+/// Tags only differing in case or in how their words are separated share a slug, but symbols
+/// are spelled out so that e.g. "C", "C++" and "C#" each get their own page. "index" is taken
+/// by the list of every tag.
+pub fn tag_slug(tag: &str) -> String {
+    let mut slug = String::with_capacity(tag.len());
+    let separate = |slug: &mut String| {
+        if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    };
+
+    for c in tag.trim().chars().flat_map(char::to_lowercase) {
+        let name = match c {
+            c if c.is_alphanumeric() => {
+                slug.push(c);
+                continue;
+            }
+            c if c.is_whitespace() || matches!(c, '-' | '_') => {
+                separate(&mut slug);
+                continue;
+            }
+            '+' => "plus".to_owned(),
+            '#' => "sharp".to_owned(),
+            '.' => "dot".to_owned(),
+            '&' => "and".to_owned(),
+            '@' => "at".to_owned(),
+            '/' => "slash".to_owned(),
+            c => format!("u{:x}", c as u32),
+        };
+        separate(&mut slug);
+        slug.push_str(&name);
+        slug.push('-');
+    }
+
+    let slug = slug.trim_matches('-');
+    if slug == "index" {
+        "index-tag".to_owned()
+    } else {
+        slug.to_owned()
+    }
+}
+
+/// A tag along with every page that uses it.
+#[derive(Debug, Clone)]
+pub struct Tag {
+    pub name: String, // name as first written in a page's front matter
+    pub pages: Vec<PageSummary>,
+}
+
+impl Tag {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            pages: Vec::new(),
+        }
+    }
+
+    pub fn sort_pages(&mut self) {
+        sort_by_date(&mut self.pages);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_are_joined_with_dashes() {
+        assert_eq!(tag_slug("Rust SIMD"), "rust-simd");
+        assert_eq!(tag_slug("  rust_simd "), "rust-simd");
+        assert_eq!(tag_slug("rust -- simd"), "rust-simd");
+    }
+
+    #[test]
+    fn symbols_get_their_own_slugs() {
+        assert_eq!(tag_slug("C"), "c");
+        assert_eq!(tag_slug("C++"), "c-plus-plus");
+        assert_eq!(tag_slug("C#"), "c-sharp");
+        assert_eq!(tag_slug("Node.js"), "node-dot-js");
+        assert_eq!(tag_slug("Q&A"), "q-and-a");
+        assert_eq!(tag_slug("what?"), "what-u3f");
+    }
+
+    #[test]
+    fn index_is_reserved() {
+        assert_eq!(tag_slug("Index"), "index-tag");
+        assert_eq!(tag_slug("index of"), "index-of");
+    }
+
+    #[test]
+    fn symbols_only_tags() {
+        assert_eq!(tag_slug("++"), "plus-plus");
+        assert_eq!(tag_slug(" - "), "");
+    }
+}
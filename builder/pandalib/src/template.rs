@@ -0,0 +1,178 @@
+use crate::{BuildError, Category, Config, Metadata, TemplateError, escape_html, tag_slug};
+use minijinja::{AutoEscape, Environment, Value, context, escape_formatter, path_loader};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Layout used by pages whose category doesn't set one.
+pub const DEFAULT_LAYOUT: &str = "page.html";
+
+/// Page layouts, loaded from the templates directory.
+///
+/// Layouts extend `base.html`, which pulls in the header, navbar and footer partials from
+/// `partials/`. Every template gets `site`, `page`, `category`, `navbar`, `prefix` (the relative
+/// path to the output root) and `content` (the rendered page body).
+pub struct Templates {
+    env: Environment<'static>,
+    dir: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+struct SiteContext<'a> {
+    title: &'a str,
+    base_url: &'a str,
+    language: &'a str,
+    author: Option<&'a str>,
+    description: &'a str,
+    feeds: Vec<FeedContext>,
+}
+
+#[derive(Debug, Serialize)]
+struct FeedContext {
+    name: &'static str,
+    mime_type: &'static str,
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct PageContext<'a> {
+    title: &'a str,
+    draft: bool,
+    date: Option<String>,
+    tags: Vec<TagContext<'a>>,
+    summary: Option<&'a str>,
+    url: String, // Canonical url of the page
+    extra: &'a toml::Table,
+}
+
+#[derive(Debug, Serialize)]
+struct TagContext<'a> {
+    name: &'a str,
+    slug: String,
+    href: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CategoryContext<'a> {
+    dir: &'a str,
+    name: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct NavContext<'a> {
+    name: &'a str,
+    href: String,
+    active: bool,
+}
+
+impl Templates {
+    pub fn new(dir: PathBuf) -> Self {
+        let mut env = Environment::new();
+        env.set_loader(path_loader(&dir));
+        env.set_formatter(|out, state, value| {
+            if value.is_safe()
+                || value.is_none()
+                || !matches!(state.auto_escape(), AutoEscape::Html)
+            {
+                return escape_formatter(out, state, value);
+            }
+            // minijinja also escapes '/', which mangles every url and relative link
+            write!(out, "{}", escape_html(&value.to_string())).map_err(minijinja::Error::from)
+        });
+        Self { env, dir }
+    }
+
+    /// Renders the page at `path` (relative to the output directory) with its category's
+    /// layout.
+    pub fn render(
+        &self,
+        config: &Config,
+        path: &Path,
+        metadata: &Metadata,
+        category: Option<&Category>,
+        prefix: &str,
+        content: &str,
+    ) -> Result<String, BuildError> {
+        let layout = category
+            .and_then(|category| category.layout.as_deref())
+            .unwrap_or(DEFAULT_LAYOUT);
+
+        let site = SiteContext {
+            title: &config.title,
+            base_url: &config.base_url,
+            language: &config.language,
+            author: config.author.as_deref(),
+            description: &config.description,
+            feeds: config
+                .feeds
+                .iter()
+                .map(|format| FeedContext {
+                    name: format.name(),
+                    mime_type: format.mime_type(),
+                    url: config.url(format.file_name()),
+                })
+                .collect(),
+        };
+
+        let page = PageContext {
+            title: &metadata.title,
+            draft: metadata.draft,
+            date: metadata
+                .date
+                .map(|date| date.format("%Y-%m-%d").to_string()),
+            tags: metadata
+                .tags
+                .iter()
+                .map(|tag| {
+                    let slug = tag_slug(tag);
+                    TagContext {
+                        name: tag,
+                        href: config.page_href(Path::new(&format!("tags/{slug}.html")), prefix),
+                        slug,
+                    }
+                })
+                .collect(),
+            summary: metadata.summary.as_deref(),
+            url: config.page_url(path),
+            extra: &metadata.extra,
+        };
+
+        let navbar: Vec<NavContext> = config
+            .navbar()
+            .into_iter()
+            .map(|nav| NavContext {
+                name: &nav.name,
+                href: config.page_href(&nav.index_path(), prefix),
+                active: category.is_some_and(|category| category.dir == nav.dir),
+            })
+            .collect();
+
+        let category = category.map(|category| CategoryContext {
+            dir: &category.dir,
+            name: &category.name,
+        });
+
+        let ctx = context! {
+            site,
+            page,
+            category,
+            navbar,
+            prefix,
+            content => Value::from_safe_string(content.to_owned()),
+        };
+
+        self.env
+            .get_template(layout)
+            .and_then(|template| template.render(ctx))
+            .map_err(|e| BuildError::Template(self.error(layout, e)))
+    }
+
+    fn error(&self, layout: &str, e: minijinja::Error) -> TemplateError {
+        let name = e.name().unwrap_or(layout);
+        let message = match e.detail() {
+            Some(detail) => format!("{}: {}", e.kind(), detail),
+            None => e.kind().to_string(),
+        };
+
+        TemplateError::new(&self.dir.join(name), e.line().unwrap_or(1), message)
+    }
+}
@@ -0,0 +1,127 @@
+use crate::Config;
+use std::path::{Path, PathBuf};
+
+/// Every url of the site is built here, so that feeds, canonical links, navigation and page
+/// lists all agree on how a page is addressed.
+///
+/// Pages are known by their path relative to the output directory as if urls weren't pretty,
+/// e.g. `posts/foo.html`. With `pretty_urls` that page is written to `posts/foo/index.html`
+/// and linked to as `posts/foo/`, and category indexes like `posts/posts.html` become
+/// `posts/index.html`.
+impl Config {
+    /// Absolute url of the file at `path`, relative to the output directory.
+    pub fn url(&self, path: &str) -> String {
+        format!(
+            "{}/{}",
+            self.base_url,
+            percent_encode(path.trim_start_matches('/'))
+        )
+    }
+
+    /// Where the page at `path` is written, relative to the output directory.
+    pub fn output_path(&self, path: &Path) -> PathBuf {
+        if !self.pretty_urls || path.file_name().is_some_and(|name| name == "index.html") {
+            return path.to_owned();
+        }
+
+        let is_category_index = self
+            .categories
+            .iter()
+            .any(|category| !category.dir.is_empty() && category.index_path() == path);
+        if is_category_index {
+            return path.with_file_name("index.html");
+        }
+
+        path.with_extension("").join("index.html")
+    }
+
+    /// Absolute url of the page at `path`.
+    pub fn page_url(&self, path: &Path) -> String {
+        self.url(&self.page_link(path))
+    }
+
+    /// Link to the page at `path` from a page `prefix` away from the root of the site.
+    pub fn page_href(&self, path: &Path, prefix: &str) -> String {
+        let href = format!("{prefix}{}", percent_encode(&self.page_link(path)));
+        if href.is_empty() {
+            "./".to_owned()
+        } else {
+            href
+        }
+    }
+
+    /// `url` as a link relative to the root of the site, e.g. `/posts/foo.html`, if it's an
+    /// absolute url of this site.
+    pub fn site_link(&self, url: &str) -> Option<String> {
+        let rest = url.strip_prefix(&self.base_url)?;
+        if !rest.is_empty() && !rest.starts_with(['/', '?', '#']) {
+            // Another site sharing a prefix, like `https://foo.com` for `https://foo.co`
+            return None;
+        }
+        Some(format!("/{}", rest.strip_prefix('/').unwrap_or(rest)))
+    }
+
+    /// Path of the page at `path` in links, relative to the root of the site.
+    fn page_link(&self, path: &Path) -> String {
+        let link = self.output_path(path).to_string_lossy().replace('\\', "/");
+        if self.pretty_urls {
+            link.strip_suffix("index.html").unwrap_or(&link).to_owned()
+        } else {
+            link
+        }
+    }
+}
+
+/// Percent encodes every character of `path` which isn't allowed in a url path, leaving the `/`
+/// between its segments untouched.
+pub fn percent_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~'
+            | b'/'
+            | b'!'
+            | b'$'
+            | b'&'
+            | b'\''
+            | b'('
+            | b')'
+            | b'*'
+            | b'+'
+            | b','
+            | b';'
+            | b'='
+            | b':'
+            | b'@' => encoded.push(byte as char),
+            byte => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    encoded
+}
+
+/// Decodes the `%XX` escapes of `s`, if they make up valid utf-8.
+pub fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' && tail.len() >= 2 {
+            let hex = std::str::from_utf8(&tail[..2]).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
@@ -1,9 +1,192 @@
-use pandalib::builder::Site;
+use clap::{Args, Parser, Subcommand};
+use pandalib::{BuildError, Config, Verbosity, builder::Site};
 use std::path::PathBuf;
+use std::process::ExitCode;
 
-fn main() {
-    let root = PathBuf::from("../src");
-    let to = PathBuf::from("../output");
+/// Exit code of a build which found errors in the site.
+const SITE_ERRORS: u8 = 1;
+/// Exit code of an invalid invocation, same as for invalid arguments.
+const USAGE_ERROR: u8 = 2;
 
-    let _ = Site::build(to, root);
+/// Static site generator for the blog.
+#[derive(Parser)]
+#[command(
+    version,
+    after_help = "Exit codes: 0 on success, 1 if the site has errors, 2 on invalid usage."
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    options: Options,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Build the site into the output directory (the default)
+    Build,
+    /// Build and serve the site, rebuilding it and reloading open pages on changes
+    Serve {
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        #[arg(short, long, default_value_t = 8000)]
+        port: u16,
+    },
+    /// Look for errors, including broken external links and invalid feeds, without touching the
+    /// output
+    Check {
+        /// Don't check external links
+        #[arg(long)]
+        offline: bool,
+    },
+    /// Create a new draft page
+    New {
+        /// Path of the page inside the source directory, e.g. `posts/my-post.md`
+        path: PathBuf,
+        /// Title of the page, defaults to its file name
+        #[arg(long)]
+        title: Option<String>,
+    },
+    /// Remove the output directory
+    Clean,
+}
+
+#[derive(Args)]
+struct Options {
+    /// Directory of the site, defaults to the closest one with a `site.toml` from the current
+    /// directory up
+    #[arg(long, global = true)]
+    root: Option<PathBuf>,
+    /// Config file, defaults to `site.toml` inside the site's directory
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Directory to build the site into, instead of the config's `output`
+    #[arg(long, global = true)]
+    out: Option<PathBuf>,
+    /// Render draft pages too
+    #[arg(long, global = true)]
+    drafts: bool,
+    /// Fail on invalid links instead of only reporting them
+    #[arg(long, global = true)]
+    strict: bool,
+    /// Print what's done to every file
+    #[arg(short, long, global = true, conflicts_with = "quiet")]
+    verbose: bool,
+    /// Only print errors
+    #[arg(short, long, global = true)]
+    quiet: bool,
+}
+
+impl Options {
+    /// Applies the flags which override the config file.
+    fn apply(&self, config: &mut Config) {
+        if let Some(out) = &self.out {
+            config.output = out.clone();
+        }
+        config.drafts |= self.drafts;
+        config.links.strict |= self.strict;
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let options = cli.options;
+
+    pandalib::set_verbosity(if options.quiet {
+        Verbosity::Quiet
+    } else if options.verbose {
+        Verbosity::Verbose
+    } else {
+        Verbosity::Normal
+    });
+
+    let Some(config_path) = config_path(&options) else {
+        eprintln!("error: couldn't find a site.toml in the current directory or its parents");
+        return ExitCode::from(USAGE_ERROR);
+    };
+
+    let mut config = match Config::load(&config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(SITE_ERRORS);
+        }
+    };
+
+    let result = match cli.command.unwrap_or(Command::Build) {
+        Command::Build => {
+            options.apply(&mut config);
+            Site::build(config)
+        }
+        Command::Serve { host, port } => pandalib::serve(
+            &config_path,
+            config,
+            &format!("{}:{}", host, port),
+            move |config| options.apply(config),
+        ),
+        Command::Check { offline } => {
+            options.apply(&mut config);
+            check(config, offline)
+        }
+        Command::New { path, title } => pandalib::create_page(&config, &path, title.as_deref())
+            .map(|file| {
+                println!("Created {}", file.display());
+            }),
+        Command::Clean => {
+            options.apply(&mut config);
+            Site::clean(&config)
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(SITE_ERRORS)
+        }
+    }
+}
+
+/// The config given with `--config`, or the `site.toml` of the site's directory.
+fn config_path(options: &Options) -> Option<PathBuf> {
+    if let Some(config) = &options.config {
+        return Some(config.clone());
+    }
+
+    if let Some(root) = &options.root {
+        return Some(root.join("site.toml"));
+    }
+
+    // Kept relative, for paths in messages to stay short
+    let cwd = std::env::current_dir().ok()?;
+    let depth = cwd
+        .ancestors()
+        .position(|dir| dir.join("site.toml").is_file())?;
+    Some(
+        std::iter::repeat_n("..", depth)
+            .collect::<PathBuf>()
+            .join("site.toml"),
+    )
+}
+
+#[cfg(feature = "external-links")]
+fn check(config: Config, offline: bool) -> Result<(), BuildError> {
+    if offline {
+        return Site::check(config, None);
+    }
+
+    let validator = pandalib::ExternalLinkValidator::from_config(&config.external_links);
+    Site::check(config, Some(&validator))
+}
+
+#[cfg(not(feature = "external-links"))]
+fn check(config: Config, offline: bool) -> Result<(), BuildError> {
+    if !offline {
+        eprintln!(
+            "warning: external links aren't checked, panda was built without the \
+            `external-links` feature"
+        );
+    }
+    Site::check(config, None)
 }